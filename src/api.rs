@@ -0,0 +1,375 @@
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use chrono::Utc;
+use log::{error, info, warn};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Response, Server};
+
+use crate::core::history::History;
+use crate::core::notifier::LogNotifier;
+use crate::core::task::{Source, Task, TaskStatus};
+use crate::core::torrent::Torrent;
+use crate::core::traits::DownloadingController;
+use crate::services::API_CONTENT_TYPE;
+
+const TORRENT_CONTENT_TYPE: &str = "application/x-bittorrent";
+const INTERACTIONS_PATH: &str = "/interactions";
+// Requester of the tasks submitted through the API
+const API_USER: &str = "api";
+
+#[derive(Deserialize)]
+struct TaskRequest {
+    uri: String,
+    destination: Option<String>,
+}
+
+pub struct ApiResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl ApiResponse {
+    pub fn new(status: u16, body: Value) -> Self {
+        Self { status, body }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::new(status, json!({ "error": message }))
+    }
+}
+
+// Answers the requests of discord to the interactions endpoint, given
+// their signature, timestamp and body
+type InteractionHandler<'a> = Box<dyn Fn(Option<&str>, Option<&str>, &[u8]) -> ApiResponse + 'a>;
+
+pub struct ApiServer<'a, T: DownloadingController> {
+    controller: &'a T,
+    token: String,
+    notifier: LogNotifier,
+    interactions: Option<InteractionHandler<'a>>,
+    // state file shared with the runs, and the NAS of the controller
+    history: Option<(PathBuf, String)>,
+}
+
+impl<'a, T: DownloadingController> ApiServer<'a, T> {
+    pub fn new(controller: &'a T, token: String) -> Self {
+        Self {
+            controller,
+            token,
+            notifier: LogNotifier,
+            interactions: None,
+            history: None,
+        }
+    }
+
+    pub fn with_history(mut self, path: PathBuf, nas: String) -> Self {
+        self.history = Some((path, nas));
+        self
+    }
+
+    pub fn with_interactions(
+        mut self,
+        handler: impl Fn(Option<&str>, Option<&str>, &[u8]) -> ApiResponse + 'a,
+    ) -> Self {
+        self.interactions = Some(Box::new(handler));
+        self
+    }
+
+    pub fn serve(&self, listen: &str) {
+        let server = match Server::http(listen) {
+            Ok(server) => server,
+            Err(e) => {
+                error!("Could not listen on {listen}: {e}");
+                return;
+            }
+        };
+        info!("REST API listening on {listen}");
+
+        for mut request in server.incoming_requests() {
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv(name))
+                    .map(|h| h.value.to_string())
+            };
+            let authorization = header("Authorization");
+            let content_type = header("Content-Type");
+            let signature = header("X-Signature-Ed25519");
+            let timestamp = header("X-Signature-Timestamp");
+            let mut body = Vec::new();
+            if let Err(e) = request.as_reader().read_to_end(&mut body) {
+                warn!("Could not read request body: {e}");
+            }
+
+            let resp = match (&self.interactions, request.method().as_str(), request.url()) {
+                // signed by discord instead of the bearer token
+                (Some(interactions), "POST", INTERACTIONS_PATH) => {
+                    interactions(signature.as_deref(), timestamp.as_deref(), &body)
+                }
+                _ => self.handle(
+                    request.method().as_str(),
+                    request.url(),
+                    authorization.as_deref(),
+                    content_type.as_deref(),
+                    body,
+                ),
+            };
+            info!("{} {} -> {}", request.method(), request.url(), resp.status);
+            let response = Response::from_string(resp.body.to_string())
+                .with_status_code(resp.status)
+                .with_header(Header::from_bytes("Content-Type", API_CONTENT_TYPE).unwrap());
+            if let Err(e) = request.respond(response) {
+                warn!("Could not answer API request: {e}");
+            }
+        }
+    }
+
+    pub fn handle(
+        &self,
+        method: &str,
+        url: &str,
+        authorization: Option<&str>,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> ApiResponse {
+        if authorization != Some(format!("Bearer {}", self.token).as_str()) {
+            return ApiResponse::error(401, "Invalid or missing bearer token");
+        }
+        let url = match Url::parse(&format!("http://localhost{url}")) {
+            Ok(url) => url,
+            Err(_) => return ApiResponse::error(400, "Invalid URL"),
+        };
+        let segments: Vec<&str> = url
+            .path()
+            .trim_matches('/')
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        match (method, segments.as_slice()) {
+            ("GET", ["tasks"]) => match self.controller.list_tasks() {
+                Some(tasks) => ApiResponse::new(200, json!(tasks)),
+                None => ApiResponse::error(502, "Could not list Download Station tasks"),
+            },
+            ("POST", ["tasks"]) => self.create_task(&url, content_type, body),
+            ("GET", ["tasks", id]) => match self.controller.get_task(id) {
+                Some(task) => ApiResponse::new(200, json!(task)),
+                None => ApiResponse::error(404, "No such task"),
+            },
+            ("DELETE", ["tasks", id]) => {
+                if self.controller.delete_task(id) {
+                    ApiResponse::new(200, json!({ "deleted": id }))
+                } else {
+                    ApiResponse::error(404, "Could not delete task")
+                }
+            }
+            (_, ["tasks"]) | (_, ["tasks", _]) => ApiResponse::error(405, "Method not allowed"),
+            _ => ApiResponse::error(404, "Not found"),
+        }
+    }
+
+    fn create_task(&self, url: &Url, content_type: Option<&str>, body: Vec<u8>) -> ApiResponse {
+        let (source, destination) = if content_type == Some(TORRENT_CONTENT_TYPE) {
+            let destination = url
+                .query_pairs()
+                .find(|(k, _)| k == "destination")
+                .map(|(_, v)| v.to_string());
+            if let Err(e) = Torrent::parse(&body) {
+                return ApiResponse::error(400, &e);
+            }
+            (Source::FILE(Bytes::from(body)), destination)
+        } else {
+            let req: TaskRequest = match serde_json::from_slice(&body) {
+                Ok(req) => req,
+                Err(e) => return ApiResponse::error(400, &format!("Invalid body: {e}")),
+            };
+            let source = match Source::from_uri(&req.uri) {
+                Ok(source) => source,
+                Err(e) => return ApiResponse::error(400, &e),
+            };
+            (source, req.destination)
+        };
+
+        let mut task = Task::new(
+            source,
+            // ids of the history, unique even for requests in a row
+            format!(
+                "api-{}",
+                Utc::now().timestamp_nanos_opt().unwrap_or_default()
+            ),
+            String::from(API_USER),
+            &self.notifier,
+            destination,
+            String::from(API_USER),
+        );
+        let mut history = match &self.history {
+            Some((path, nas)) => {
+                task.nas = Some(nas.clone());
+                History::load(path.clone())
+            }
+            None => History::default(),
+        };
+        // API tasks are not followed, the backend tells how they went
+        let remote = self.controller.list_tasks().unwrap_or_default();
+        history.refresh(API_USER, &remote);
+        if let Some(reply) = history.duplicate(&task, &remote) {
+            info!("Task {} is a duplicate: {reply}", task.message_id);
+            return ApiResponse::error(409, &reply);
+        }
+        self.controller.submit_task(&mut task);
+        if task.get_status() != TaskStatus::FAILED {
+            history.record(&task);
+        }
+        history.save();
+
+        match task.get_status() {
+            TaskStatus::SUBMITTED => ApiResponse::new(
+                202,
+                json!({ "request_id": task.message_id, "status": task.get_status() }),
+            ),
+            status => ApiResponse::new(
+                502,
+                json!({ "request_id": task.message_id, "status": status, "error": "Download Station refused the task" }),
+            ),
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::{env, fs};
+
+    use serde_json::json;
+
+    use crate::{
+        api::ApiServer,
+        core::history::History,
+        core::task::tests::{remote, DsControlerMock},
+        core::torrent::tests::torrent_file,
+    };
+
+    fn controller() -> DsControlerMock {
+        DsControlerMock {
+            tasks: vec![remote("dbid_1", "ubuntu")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rejects_missing_or_wrong_token() {
+        let controler = controller();
+        let api = ApiServer::new(&controler, "secret".to_string());
+        assert!(api.handle("GET", "/tasks", None, None, vec![]).status == 401);
+        assert!(
+            api.handle("GET", "/tasks", Some("Bearer nope"), None, vec![])
+                .status
+                == 401
+        );
+        assert!(
+            api.handle("GET", "/tasks", Some("Bearer secret"), None, vec![])
+                .status
+                == 200
+        );
+    }
+
+    #[test]
+    fn routes_tasks() {
+        let controler = controller();
+        let api = ApiServer::new(&controler, "secret".to_string());
+        let auth = Some("Bearer secret");
+
+        let resp = api.handle("GET", "/tasks/dbid_1", auth, None, vec![]);
+        assert!(resp.status == 200);
+        assert!(resp.body["status"] == "DOWNLOADING");
+        assert!(
+            api.handle("GET", "/tasks/dbid_2", auth, None, vec![])
+                .status
+                == 404
+        );
+        assert!(
+            api.handle("DELETE", "/tasks/dbid_1", auth, None, vec![])
+                .status
+                == 200
+        );
+        assert!(api.handle("PUT", "/tasks", auth, None, vec![]).status == 405);
+        assert!(api.handle("GET", "/other", auth, None, vec![]).status == 404);
+    }
+
+    #[test]
+    fn creates_tasks() {
+        let controler = controller();
+        let api = ApiServer::new(&controler, "secret".to_string());
+        let auth = Some("Bearer secret");
+
+        let body = json!({"uri": "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a", "destination": "videos/Movies"});
+        let resp = api.handle("POST", "/tasks", auth, None, body.to_string().into_bytes());
+        assert!(resp.status == 202);
+        assert!(resp.body["status"] == "SUBMITTED");
+
+        let body = json!({"uri": "not a link"});
+        let resp = api.handle("POST", "/tasks", auth, None, body.to_string().into_bytes());
+        assert!(resp.status == 400);
+
+        let resp = api.handle(
+            "POST",
+            "/tasks?destination=videos",
+            auth,
+            Some("application/x-bittorrent"),
+            torrent_file(),
+        );
+        assert!(resp.status == 202);
+        let resp = api.handle(
+            "POST",
+            "/tasks",
+            auth,
+            Some("application/x-bittorrent"),
+            b"d4:infod4:name1:aee".to_vec(),
+        );
+        assert!(resp.status == 400);
+    }
+
+    #[test]
+    fn records_tasks() {
+        let path = env::temp_dir().join(format!("ds-companion-api-{}.json", std::process::id()));
+        let controler = controller();
+        let api = ApiServer::new(&controler, "secret".to_string())
+            .with_history(path.clone(), "home".to_string());
+        let auth = Some("Bearer secret");
+
+        let body = json!({"uri": "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a"})
+            .to_string()
+            .into_bytes();
+        let resp = api.handle("POST", "/tasks", auth, None, body.clone());
+        assert!(resp.status == 202);
+        let history = History::load(path.clone());
+        let entry = history
+            .find(resp.body["request_id"].as_str().unwrap())
+            .unwrap();
+        assert!(entry.nas.as_deref() == Some("home") && entry.user_id == "api");
+
+        // the mock never lists it: once gone from Download Station, it
+        // may be requested again
+        let resp = api.handle("POST", "/tasks", auth, None, body);
+        assert!(resp.status == 202);
+        assert!(History::load(path.clone()).entries().count() == 2);
+
+        // uploaded torrents already in Download Station
+        let resp = api.handle(
+            "POST",
+            "/tasks",
+            auth,
+            Some("application/x-bittorrent"),
+            b"d4:infod6:lengthi42e4:name6:ubuntu12:piece lengthi1024eee".to_vec(),
+        );
+        assert!(resp.status == 409);
+        assert!(resp.body["error"] == "already downloading (50%)");
+        fs::remove_file(&path).unwrap();
+        let _ = fs::remove_file(path.with_extension("json.lock"));
+    }
+}
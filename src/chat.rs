@@ -0,0 +1,345 @@
+use chrono::{DateTime, Utc};
+
+use crate::core::command::ChatCommand;
+use crate::core::search::{Search, SearchResult, Searches};
+use crate::core::speed::{parse_limit, Boost, SpeedLimits, Turbo};
+use crate::core::stats::Overview;
+use crate::core::traits::{
+    DownloadingController, RssController, SearchController, SpeedController, StorageController,
+};
+use crate::core::units::parse_duration;
+
+const HELP: &str = "Commands:
+• !stats (or !status): speeds, free space and active tasks of the NAS
+• !limit: speed limits of the NAS
+• !limit <download> [upload]: set them, e.g. !limit 2MB 500KB, or !limit off (admins)
+• !schedule [on|off]: the alternative speed schedule (admins to change it)
+• !turbo [duration|off]: lift the limits for a while, 1h by default (admins)
+• !rss: RSS sites of Download Station
+• !rss items <id>: their latest items
+• !rss refresh [id]: update one or every site
+• !rss add <url>, !rss remove <id>: subscribe or unsubscribe (admins)
+• !search [in:<category>] <terms>: look for torrents
+• !get <number>: download one of the results of your last search
+• !help: this message";
+// Items listed by !rss items
+const MAX_LISTED_ITEMS: usize = 10;
+// Search results offered
+const MAX_RESULTS: usize = 10;
+// Prefix of the category of !search
+const CATEGORY_PREFIX: &str = "in:";
+const DEFAULT_TURBO: &str = "1h";
+
+// What a command gets back
+pub enum Reply {
+    Text(String),
+    // results for the user to pick from
+    Results(Search),
+    // the result picked, to download
+    Download(SearchResult),
+}
+
+// Execute a command posted in a chat channel of the NAS
+pub fn execute<T>(
+    controller: &T,
+    command: &ChatCommand,
+    nas: &str,
+    turbo: &mut Turbo,
+    searches: &mut Searches,
+) -> Reply
+where
+    T: DownloadingController
+        + StorageController
+        + SpeedController
+        + RssController
+        + SearchController,
+{
+    match command.name.as_str() {
+        "search" => search(controller, command, searches),
+        "get" | "pick" => pick(command, searches),
+        _ => Reply::Text(answer(controller, command, nas, turbo)),
+    }
+}
+
+// Commands replied with a text
+fn answer<T>(controller: &T, command: &ChatCommand, nas: &str, turbo: &mut Turbo) -> String
+where
+    T: DownloadingController + StorageController + SpeedController + RssController,
+{
+    let args = &command.args;
+    match command.name.as_str() {
+        "stats" | "status" => match Overview::fetch(controller) {
+            Ok(overview) => overview.to_string(),
+            Err(e) => e,
+        },
+        "limit" | "limits" if args.is_empty() => show_limits(controller, nas, turbo),
+        "schedule" if args.is_empty() => match controller.schedule_enabled() {
+            Some(enabled) => format!("Alternative speed schedule: {}", on_off(enabled)),
+            None => String::from("Could not read the schedule"),
+        },
+        "rss" => rss(controller, command),
+        "limit" | "limits" | "schedule" | "turbo" if !command.admin => format!(
+            "Sorry <@{}>, only admins may change the NAS settings",
+            command.user_id
+        ),
+        "limit" | "limits" => set_limits(controller, args, nas, turbo),
+        "schedule" => {
+            let enabled = match args[0].to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => return String::from("Expects !schedule on or !schedule off"),
+            };
+            match controller.set_schedule(enabled) {
+                Ok(()) => format!("Alternative speed schedule {}", on_off(enabled)),
+                Err(e) => format!("Could not change the schedule: {e}"),
+            }
+        }
+        "turbo" => boost(controller, command, nas, turbo),
+        "help" => HELP.to_string(),
+        name => format!("Unknown command !{name}, try !help"),
+    }
+}
+
+fn search<T: SearchController>(
+    controller: &T,
+    command: &ChatCommand,
+    searches: &mut Searches,
+) -> Reply {
+    let (categories, terms): (Vec<&String>, Vec<&String>) = command
+        .args
+        .iter()
+        .partition(|arg| arg.starts_with(CATEGORY_PREFIX));
+    let category = categories
+        .first()
+        .map(|c| c.trim_start_matches(CATEGORY_PREFIX));
+    let terms = terms
+        .iter()
+        .map(|t| t.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if terms.is_empty() {
+        return Reply::Text(String::from("Expects !search [in:<category>] <terms>"));
+    }
+    let results = match controller.search(&terms, category) {
+        Ok(results) if results.is_empty() => return Reply::Text(format!("No result for {terms}")),
+        Ok(results) => results.into_iter().take(MAX_RESULTS).collect(),
+        Err(e) => return Reply::Text(format!("Could not search: {e}")),
+    };
+    let search = Search {
+        message_id: command.message_id.clone(),
+        channel_id: command.channel_id.clone(),
+        user_id: command.user_id.clone(),
+        terms,
+        results,
+        searched_at: Utc::now().timestamp(),
+    };
+    searches.record(search.clone());
+    Reply::Results(search)
+}
+
+fn pick(command: &ChatCommand, searches: &Searches) -> Reply {
+    let search = match searches.last(
+        &command.channel_id,
+        &command.user_id,
+        Utc::now().timestamp(),
+    ) {
+        Some(search) => search,
+        None => return Reply::Text(String::from("No search to pick from, try !search <terms>")),
+    };
+    let picked = command
+        .args
+        .first()
+        .and_then(|n| n.parse().ok())
+        .and_then(|n| search.pick(n));
+    match picked {
+        Some(result) => Reply::Download(result.clone()),
+        None => Reply::Text(format!(
+            "Expects !get and a number from 1 to {}",
+            search.results.len()
+        )),
+    }
+}
+
+fn rss<T: RssController>(controller: &T, command: &ChatCommand) -> String {
+    let args: Vec<&str> = command.args.iter().map(String::as_str).collect();
+    match args[..] {
+        [] | ["list"] => match controller.list_rss_sites() {
+            Some(sites) if sites.is_empty() => String::from("No RSS site"),
+            Some(sites) => sites
+                .iter()
+                .map(|site| site.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => String::from("Could not list the RSS sites"),
+        },
+        ["items", id] => match controller.list_rss_items(id) {
+            Some(items) if items.is_empty() => format!("No item in RSS site #{id}"),
+            Some(items) => items
+                .iter()
+                .take(MAX_LISTED_ITEMS)
+                .map(|item| format!("• {item}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => format!("Could not list the items of RSS site #{id}"),
+        },
+        ["refresh"] => outcome(
+            controller.refresh_rss_site(None),
+            "RSS sites refreshed",
+            "refresh the RSS sites",
+        ),
+        ["refresh", id] => outcome(
+            controller.refresh_rss_site(Some(id)),
+            &format!("RSS site #{id} refreshed"),
+            &format!("refresh RSS site #{id}"),
+        ),
+        ["add", _] | ["remove", _] if !command.admin => format!(
+            "Sorry <@{}>, only admins may change the RSS sites",
+            command.user_id
+        ),
+        ["add", url] => outcome(
+            controller.add_rss_site(url),
+            &format!("RSS site {url} added"),
+            &format!("add {url}"),
+        ),
+        ["remove", id] => outcome(
+            controller.delete_rss_site(id),
+            &format!("RSS site #{id} removed"),
+            &format!("remove RSS site #{id}"),
+        ),
+        _ => String::from(
+            "Expects !rss, !rss items <id>, !rss refresh [id], !rss add <url> or !rss remove <id>",
+        ),
+    }
+}
+
+fn outcome(result: Result<(), String>, success: &str, action: &str) -> String {
+    match result {
+        Ok(()) => success.to_string(),
+        Err(e) => format!("Could not {action}: {e}"),
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    match enabled {
+        true => "enabled",
+        false => "disabled",
+    }
+}
+
+fn time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn show_limits<T: SpeedController>(controller: &T, nas: &str, turbo: &Turbo) -> String {
+    let mut lines = vec![match controller.speed_limits() {
+        Some(limits) => format!("Speed limits: {limits}"),
+        None => String::from("Could not read the speed limits"),
+    }];
+    if let Some(enabled) = controller.schedule_enabled() {
+        lines.push(format!("Alternative speed schedule: {}", on_off(enabled)));
+    }
+    if let Some(boost) = turbo.get(nas) {
+        lines.push(format!(
+            "Turbo until {}, then back to {}",
+            time(boost.until),
+            boost.limits
+        ));
+    }
+    lines.join("\n")
+}
+
+fn set_limits<T: SpeedController>(
+    controller: &T,
+    args: &[String],
+    nas: &str,
+    turbo: &mut Turbo,
+) -> String {
+    let parsed: Vec<Option<u64>> = args.iter().take(2).map(|a| parse_limit(a)).collect();
+    if parsed.iter().any(Option::is_none) {
+        return String::from("Expects speeds such as !limit 2MB 500KB, or !limit off");
+    }
+    let apply = |limits: SpeedLimits| {
+        let limits = limits.with_download(parsed[0].unwrap());
+        match parsed.get(1) {
+            Some(upload) => limits.with_upload(upload.unwrap()),
+            None => limits,
+        }
+    };
+    // the turbo keeps the limits lifted, they apply once it is over
+    if let Some(boost) = turbo.get_mut(nas) {
+        boost.limits = apply(boost.limits);
+        return format!(
+            "Speed limits will be {} once the turbo is over, at {}",
+            boost.limits,
+            time(boost.until)
+        );
+    }
+    let limits = match controller.speed_limits() {
+        Some(limits) => apply(limits),
+        None => return String::from("Could not read the speed limits"),
+    };
+    match controller.set_speed_limits(&limits) {
+        Ok(()) => format!("Speed limits: {limits}"),
+        Err(e) => format!("Could not change the speed limits: {e}"),
+    }
+}
+
+// Lift the limits and the schedule until the duration is over,
+// the runs after that restore them
+fn boost<T: SpeedController>(
+    controller: &T,
+    command: &ChatCommand,
+    nas: &str,
+    turbo: &mut Turbo,
+) -> String {
+    let arg = command
+        .args
+        .first()
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_TURBO);
+    if arg.eq_ignore_ascii_case("off") {
+        return match turbo.stop(nas) {
+            Some(boost) => match boost.restore(controller) {
+                Ok(()) => format!("Turbo over, speed limits: {}", boost.limits),
+                Err(e) => {
+                    turbo.start(boost);
+                    format!("Could not restore the speed limits: {e}")
+                }
+            },
+            None => String::from("No turbo in progress"),
+        };
+    }
+    let duration = match parse_duration(arg) {
+        Some(duration) if duration > 0 => duration,
+        _ => return String::from("Expects a duration such as !turbo 1h, or !turbo off"),
+    };
+    let until = Utc::now().timestamp() + duration as i64;
+    if let Some(boost) = turbo.get_mut(nas) {
+        boost.until = until;
+        return format!("Turbo extended until {}", time(until));
+    }
+    let (limits, schedule) = match (controller.speed_limits(), controller.schedule_enabled()) {
+        (Some(limits), Some(schedule)) => (limits, schedule),
+        _ => return String::from("Could not read the speed limits"),
+    };
+    let boost = Boost {
+        nas: nas.to_string(),
+        until,
+        limits,
+        schedule,
+        channel_id: command.channel_id.clone(),
+        message_id: command.message_id.clone(),
+        user_id: command.user_id.clone(),
+    };
+    let lifted = controller
+        .set_speed_limits(&SpeedLimits::default())
+        .and_then(|()| controller.set_schedule(false));
+    if let Err(e) = lifted {
+        let _ = boost.restore(controller);
+        return format!("Could not lift the speed limits: {e}");
+    }
+    turbo.start(boost);
+    format!("Turbo until {}, then back to {limits}", time(until))
+}
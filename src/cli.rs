@@ -0,0 +1,297 @@
+use std::{fs, path::Path, thread, time::Duration};
+
+use bytes::Bytes;
+use chrono::Utc;
+use serde_json::json;
+
+use crate::conf::{Command, Conf};
+use crate::core::cleanup;
+use crate::core::destination;
+use crate::core::history::History;
+use crate::core::notifier::LogNotifier;
+use crate::core::stats::Overview;
+use crate::core::task::{RemoteTask, Source, Task, TaskStatus};
+use crate::core::torrent::Torrent;
+use crate::core::traits::{DownloadingController, StorageController};
+use crate::core::units::human_size;
+
+// Requester of the tasks submitted from the command line
+const CLI_USER: &str = "cli";
+
+// Execute a Download Station command from the NAS shell. Returns
+// the message to display on failure
+pub fn execute<T: DownloadingController + StorageController>(
+    controller: &T,
+    command: &Command,
+    conf: &Conf,
+) -> Result<(), String> {
+    let json = conf.json;
+    match command {
+        Command::Add { source, to } => {
+            let status = add(controller, source, to.as_deref(), conf)?;
+            if json {
+                println!("{}", json!({ "status": status }));
+            } else {
+                println!("{status}");
+            }
+            Ok(())
+        }
+        Command::List => {
+            let tasks = controller
+                .list_tasks()
+                .ok_or("Could not list Download Station tasks")?;
+            println!("{}", render(&tasks, json));
+            Ok(())
+        }
+        Command::Status { id } => {
+            let task = controller
+                .get_task(id)
+                .ok_or(format!("No such task: {id}"))?;
+            println!("{}", render(&[task], json));
+            Ok(())
+        }
+        Command::Pause { id } => action(controller.pause_task(id), "pause", id, json),
+        Command::Resume { id } => action(controller.resume_task(id), "resume", id, json),
+        Command::Delete { id } => action(controller.delete_task(id), "delete", id, json),
+        Command::Folders { path } => {
+            let path = path.as_deref().unwrap_or_default().trim_end_matches('/');
+            let path = match path.starts_with('/') || path.is_empty() {
+                true => path.to_string(),
+                false => format!("/{path}"),
+            };
+            let folders = controller
+                .list_folders(&path)
+                .ok_or(format!("Could not list {path}"))?;
+            if json {
+                println!("{}", json!(folders));
+            } else {
+                folders.iter().for_each(|f| println!("{}", f.name));
+            }
+            Ok(())
+        }
+        Command::Cleanup {
+            older_than,
+            dry_run,
+        } => {
+            let tasks = controller
+                .list_tasks()
+                .ok_or("Could not list Download Station tasks")?;
+            let expired = cleanup::expired(&tasks, *older_than, Utc::now().timestamp());
+            let mut removed = vec![];
+            for task in expired {
+                if *dry_run || controller.delete_task(&task.id) {
+                    removed.push(task.clone());
+                } else {
+                    eprintln!("Could not delete task {}", task.id);
+                }
+            }
+            println!("{}", render(&removed, json));
+            Ok(())
+        }
+        Command::Stats => {
+            let overview = Overview::fetch(controller)?;
+            if json {
+                println!("{}", json!(overview));
+            } else {
+                println!("{overview}");
+            }
+            Ok(())
+        }
+        Command::Watch { interval } => loop {
+            let tasks = controller
+                .list_tasks()
+                .ok_or("Could not list Download Station tasks")?;
+            if !json {
+                // clear the terminal
+                print!("\x1B[2J\x1B[H");
+            }
+            println!("{}", render(&tasks, json));
+            thread::sleep(Duration::from_secs(*interval));
+        },
+        Command::Run | Command::Serve => Err(String::from("Not a Download Station command")),
+    }
+}
+
+// Submit a download the way the runs do: to the alias or routed
+// destination, unless already downloaded, then kept in the history
+fn add<T: DownloadingController + StorageController>(
+    controller: &T,
+    source: &str,
+    to: Option<&str>,
+    conf: &Conf,
+) -> Result<TaskStatus, String> {
+    let source = if Path::new(source).is_file() {
+        let file = fs::read(source).map_err(|e| format!("Could not read {source}: {e}"))?;
+        Torrent::parse(&file)?;
+        Source::FILE(Bytes::from(file))
+    } else {
+        Source::from_uri(source)?
+    };
+    let to = match to {
+        Some(to) => Some(conf.router.resolve(&destination::parse(to)?)),
+        None => conf.router.route(&source),
+    };
+    let notifier = LogNotifier;
+    let mut task = Task::new(
+        source,
+        // ids of the history, unique even for commands in a row
+        format!(
+            "cli-{}",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ),
+        String::from(CLI_USER),
+        &notifier,
+        to,
+        String::from(CLI_USER),
+    );
+    task.nas = Some(conf.nas.clone().unwrap_or(conf.default_nas.clone()));
+
+    let mut history = History::load(conf.state_file.clone());
+    // CLI tasks are not followed, the backend tells how they went
+    let remote = controller.list_tasks().unwrap_or_default();
+    history.refresh(CLI_USER, &remote);
+    if let Some(reply) = history.duplicate(&task, &remote) {
+        return Err(format!("Not submitted, {reply}"));
+    }
+    if let Some(to) = &task.destination_folder {
+        destination::check(controller, to, conf.create_folders)?;
+    }
+    controller.submit_task(&mut task);
+    if task.get_status() != TaskStatus::SUBMITTED {
+        return Err(String::from("Download Station refused the task"));
+    }
+    history.record(&task);
+    history.save();
+    Ok(task.get_status())
+}
+
+fn action(done: bool, verb: &str, id: &str, json: bool) -> Result<(), String> {
+    if !done {
+        return Err(format!("Could not {verb} task {id}"));
+    }
+    if json {
+        println!("{}", json!({ "id": id, "action": verb }));
+    }
+    Ok(())
+}
+
+fn render(tasks: &[RemoteTask], json: bool) -> String {
+    if json {
+        return json!(tasks).to_string();
+    }
+    let rows: Vec<[String; 6]> = tasks
+        .iter()
+        .map(|t| {
+            [
+                t.id.clone(),
+                t.ds_status.clone(),
+                format!("{:.1}%", t.progress()),
+                human_size(t.size),
+                t.destination.clone().unwrap_or_default(),
+                t.title.clone(),
+            ]
+        })
+        .collect();
+    let header = ["ID", "STATUS", "PROGRESS", "SIZE", "DESTINATION", "TITLE"].map(String::from);
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for row in &rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    std::iter::once(&header)
+        .chain(rows.iter())
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| format!("{:<width$}", cell, width = widths[i]))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use std::{env, fs};
+
+    use crate::{
+        cli::{add, render},
+        conf::Conf,
+        core::history::History,
+        core::routing::Router,
+        core::task::tests::{remote, DsControlerMock},
+        core::task::{RemoteTask, TaskStatus},
+    };
+
+    const MAGNET: &str = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    #[test]
+    fn task_table() {
+        let tasks = vec![RemoteTask {
+            size: 2048,
+            downloaded: 512,
+            destination: Some("downloads/iso".to_string()),
+            ..remote("dbid_1", "debian-12.9.0-amd64-DVD-1.iso")
+        }];
+        let table = render(&tasks, false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines.len() == 2);
+        assert!(lines[0].starts_with("ID      STATUS"));
+        assert!(lines[1].contains("25.0%"));
+        assert!(lines[1].contains("2.0 KB"));
+
+        let json = render(&tasks, true);
+        assert!(json.contains("\"id\":\"dbid_1\""));
+    }
+
+    #[test]
+    fn adds_tasks() {
+        let path = env::temp_dir().join(format!("ds-companion-cli-{}.json", std::process::id()));
+        let conf = Conf {
+            state_file: path.clone(),
+            default_nas: String::from("home"),
+            router: Router::new(
+                BTreeMap::from([(String::from("movies"), String::from("video/Movies"))]),
+                vec![],
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let controller = DsControlerMock {
+            tasks: vec![RemoteTask {
+                uri: Some(String::from("https://example.org/ubuntu.iso")),
+                ..remote("dbid_1", "ubuntu.iso")
+            }],
+            ..Default::default()
+        };
+
+        assert!(add(&controller, MAGNET, Some("\"Movies\""), &conf) == Ok(TaskStatus::SUBMITTED));
+        assert!(controller.done.take() == ["submit to video/Movies"]);
+        let history = History::load(path.clone());
+        let entry = history.entries().next().unwrap();
+        assert!(entry.user_id == "cli" && entry.nas.as_deref() == Some("home"));
+
+        // already downloading, or not a valid destination
+        let reply = add(&controller, "https://example.org/ubuntu.iso", None, &conf);
+        assert!(reply == Err(String::from("Not submitted, already downloading (50%)")));
+        assert!(add(
+            &controller,
+            "https://example.org/debian.iso",
+            Some("../etc"),
+            &conf
+        )
+        .is_err());
+        assert!(controller.done.take().is_empty());
+        assert!(History::load(path.clone()).entries().count() == 1);
+        fs::remove_file(&path).unwrap();
+        let _ = fs::remove_file(path.with_extension("json.lock"));
+    }
+}
@@ -1,26 +1,797 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
-use once_cell::sync::Lazy;
+use clap::{Parser, Subcommand};
+use reqwest::Url;
+use serde::Deserialize;
 
+use crate::core::organize::{self, Variables};
+use crate::core::routing::Router;
+use crate::core::rss::FeedFilter;
+use crate::core::seeding::SeedPolicy;
+use crate::core::units::ByteSize;
+
+const DEFAULT_CONFIG_FILE: &str = "/etc/ds-companion.toml";
+// Name of the backend described by the synology_* settings
+const DEFAULT_NAS: &str = "default";
+const DEFAULT_STATE_FILE: &str = "/var/lib/ds-companion/history.json";
+
+// Settings given on the command line or through env vars. Everything
+// is optional here since it may come from the config file instead
 #[derive(Debug, Parser, Default)]
 #[clap(version)]
-pub struct Conf {
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML configuration file [default: /etc/ds-companion.toml]
+    #[arg(long, env = "DS_COMPANION_CONFIG")]
+    pub config: Option<PathBuf>,
     #[arg(long, env)]
-    pub discord_token: String,
+    pub discord_token: Option<String>,
+    /// File containing the discord token
     #[arg(long, env)]
-    pub discord_channel: String,
-    #[arg(short, long, default_value_t = 2, env)]
-    pub minutes_delta: usize,
+    pub discord_token_file: Option<PathBuf>,
+    #[arg(long, env)]
+    pub discord_channel: Option<String>,
+    /// How often the channel is checked, in minutes [default: 2]
+    #[arg(short, long, env)]
+    pub minutes_delta: Option<usize>,
+    #[arg(long, env)]
+    pub synology_root_api: Option<String>,
+    #[arg(long, env)]
+    pub synology_user: Option<String>,
+    #[arg(long, env)]
+    pub synology_password: Option<String>,
+    /// File containing the synology password
+    #[arg(long, env)]
+    pub synology_password_file: Option<PathBuf>,
+    /// Address the REST API listens on (e.g. 0.0.0.0:8080)
+    #[arg(long, env)]
+    pub api_listen: Option<String>,
+    /// Bearer token expected from REST API clients
+    #[arg(long, env)]
+    pub api_token: Option<String>,
+    /// File containing the REST API token
     #[arg(long, env)]
-    pub synology_root_api: String,
+    pub api_token_file: Option<PathBuf>,
+    /// Public key of the discord application, to handle button clicks on --api-listen
     #[arg(long, env)]
-    pub synology_user: String,
+    pub discord_public_key: Option<String>,
+    /// File keeping track of submitted tasks between runs
     #[arg(long, env)]
-    pub synology_password: String,
+    pub state_file: Option<PathBuf>,
+    /// Create missing destination folders instead of rejecting the task
+    #[arg(long, env, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub create_folders: Option<bool>,
+    /// Remove tasks from Download Station once completed or seeded (files are kept)
+    #[arg(long, env, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub remove_finished: Option<bool>,
+    /// Synology backend used by commands and the REST API [default: default_nas]
+    #[arg(long, env, global = true)]
+    pub nas: Option<String>,
+    /// Print command results as JSON instead of tables
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fetch new tasks from discord and follow them until completion (default)
+    Run,
+    /// Expose the REST API on --api-listen
+    Serve,
+    /// Submit a magnet link, an URL or a .torrent file
+    Add {
+        source: String,
+        /// Destination folder
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// List Download Station tasks
+    List,
+    /// Show a single task
+    Status { id: String },
+    /// Pause a task
+    Pause { id: String },
+    /// Resume a paused task
+    Resume { id: String },
+    /// Delete a task (downloaded files are kept)
+    Delete { id: String },
+    /// List the folders of a shared folder, or the shared folders
+    Folders { path: Option<String> },
+    /// Remove finished and failed tasks from the list (downloaded files are kept)
+    Cleanup {
+        /// Only tasks over for more than this many days
+        #[arg(long, default_value_t = 30)]
+        older_than: u64,
+        /// List the tasks without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Speeds, free space and active tasks of the NAS
+    Stats,
+    /// Display the task list, refreshed every few seconds
+    Watch {
+        /// Refresh interval in seconds
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+}
+
+// Content of the configuration file, same keys as the command line
+// arguments in snake_case
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FileConf {
+    pub discord_token: Option<String>,
+    pub discord_token_file: Option<PathBuf>,
+    pub discord_channel: Option<String>,
+    pub minutes_delta: Option<usize>,
+    pub synology_root_api: Option<String>,
+    pub synology_user: Option<String>,
+    pub synology_password: Option<String>,
+    pub synology_password_file: Option<PathBuf>,
+    pub api_listen: Option<String>,
+    pub api_token: Option<String>,
+    pub api_token_file: Option<PathBuf>,
+    pub discord_public_key: Option<String>,
+    #[serde(default)]
+    pub channels: Vec<ChannelConf>,
+    /// Named Download Station backends
+    #[serde(default)]
+    pub synology: BTreeMap<String, NasFileConf>,
+    pub default_nas: Option<String>,
+    #[serde(default)]
+    pub access: AccessConf,
+    #[serde(default)]
+    pub quotas: QuotaConf,
+    pub state_file: Option<PathBuf>,
+    pub create_folders: Option<bool>,
+    pub remove_finished: Option<bool>,
+    /// Destination aliases, e.g. movies = "video/Movies"
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    pub routes: Vec<RouteConf>,
+    /// Bigger downloads are refused, when their size is known beforehand
+    pub max_download_size: Option<ByteSize>,
+    #[serde(default)]
+    pub hooks: Vec<HookConf>,
+    #[serde(default)]
+    pub organize: Vec<OrganizeConf>,
+    #[serde(default)]
+    pub rss: Vec<RssConf>,
+}
+
+// What happens to messages whose author may not start downloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rejection {
+    #[default]
+    Reply,
+    Reaction,
+    Ignore,
+}
+
+// Who may start downloads, on top of the per channel allowlists. Deny
+// lists win over allow lists, empty allow lists allow everyone
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConf {
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    #[serde(default)]
+    pub allowed_roles: Vec<String>,
+    #[serde(default)]
+    pub denied_users: Vec<String>,
+    #[serde(default)]
+    pub denied_roles: Vec<String>,
+    /// May change the NAS settings through chat commands
+    #[serde(default)]
+    pub admin_users: Vec<String>,
+    #[serde(default)]
+    pub admin_roles: Vec<String>,
+    #[serde(default)]
+    pub rejection: Rejection,
+    pub audit_log: Option<PathBuf>,
+}
+
+// Destination of the tasks matching every criterion given, when the
+// message does not say where to download
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConf {
+    /// Regex matched against the torrent or file name
+    pub pattern: Option<String>,
+    /// Part of a tracker URL
+    pub tracker: Option<String>,
+    /// Extensions of the name or of one of the files
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// A folder or an alias
+    pub destination: String,
+}
+
+// Actions run once a download is over, for every task or only for
+// the ones downloaded in `folder`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConf {
+    /// Destination folder the hook is limited to
+    pub folder: Option<String>,
+    /// Shell command, given the task as DS_COMPANION_* env vars and JSON on stdin
+    pub command: Option<String>,
+    /// URL the task is POSTed to as JSON
+    pub webhook: Option<String>,
+    /// URL of a library rescan, POSTed unless prefixed with another method ("GET http://...")
+    pub rescan: Option<String>,
+}
+
+// How finished downloads are organised. The first rule whose folder
+// holds the download applies, rules without folder apply to any
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrganizeConf {
+    pub folder: Option<String>,
+    /// Where downloads are moved, e.g. "video/Movies/{title} ({year})"
+    pub path: Option<String>,
+    /// Move the file of single-file folders up, removing the folder
+    #[serde(default)]
+    pub flatten: bool,
+    /// Extract archives, as the Extract: option does
+    #[serde(default)]
+    pub extract: bool,
+}
+
+// A feed watched by ds-companion: new items whose title matches are
+// downloaded and announced in `channel`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RssConf {
+    /// RSS or Atom feed
+    pub url: String,
+    /// Regexes matched against the item titles, any title when empty
+    #[serde(default)]
+    pub filters: Vec<String>,
+    /// Regex of the titles to skip
+    pub exclude: Option<String>,
+    /// Channel the downloads are announced in, its nas and seed apply
+    pub channel: String,
+    /// User the downloads are made for, mentioned once they are over
+    pub user: String,
+    pub destination: Option<String>,
+}
+
+// Limits on what a single user may download. None means unlimited
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub max_active: Option<usize>,
+    pub max_tasks_per_day: Option<usize>,
+    pub max_bytes_per_day: Option<ByteSize>,
+    pub max_bytes_per_week: Option<ByteSize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConf {
+    #[serde(default)]
+    pub default: QuotaLimits,
+    /// Per user_id overrides of the default limits
+    #[serde(default)]
+    pub users: BTreeMap<String, QuotaLimits>,
+}
+
+impl QuotaConf {
+    pub fn limits_for(&self, user_id: &str) -> &QuotaLimits {
+        self.users.get(user_id).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NasFileConf {
+    pub root_api: String,
+    pub user: String,
+    pub password: Option<String>,
+    pub password_file: Option<PathBuf>,
+}
+
+// A discord channel to fetch tasks from, with its own settings
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConf {
+    pub id: String,
+    /// Used when the message does not specify a destination
+    pub destination: Option<String>,
+    /// When both are empty, anyone posting in the channel is allowed
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    #[serde(default)]
+    pub allowed_roles: Vec<String>,
+    /// Synology backend, unless the message says otherwise
+    pub nas: Option<String>,
+    /// Seeding policy, unless the message says otherwise
+    pub seed: Option<SeedPolicy>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiscordConf {
+    pub token: String,
+    pub channels: Vec<ChannelConf>,
+    pub minutes_delta: usize,
+    pub access: AccessConf,
+    pub router: Router,
+    // status messages carry buttons, their clicks being handled
+    pub buttons: bool,
+    // finished downloads leave Download Station, nothing left to act on
+    pub remove_finished: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SynologyConf {
+    pub root_api: String,
+    pub user: String,
+    pub password: String,
+}
+
+// Resolved configuration: command line > env vars > config file
+#[derive(Debug, Default)]
+pub struct Conf {
+    pub command: Option<Command>,
+    pub discord_token: Option<String>,
+    pub discord_channels: Vec<ChannelConf>,
+    pub minutes_delta: usize,
+    pub synology: BTreeMap<String, SynologyConf>,
+    pub default_nas: String,
+    pub nas: Option<String>,
+    pub api_listen: Option<String>,
+    pub api_token: Option<String>,
+    pub discord_public_key: Option<String>,
+    pub access: AccessConf,
+    pub quotas: QuotaConf,
+    pub state_file: PathBuf,
+    pub create_folders: bool,
+    pub remove_finished: bool,
+    pub router: Router,
+    pub max_download_size: Option<ByteSize>,
+    pub hooks: Vec<HookConf>,
+    pub organize: Vec<OrganizeConf>,
+    pub rss: Vec<RssConf>,
+    pub json: bool,
+}
+
+fn read_secret(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|e| format!("could not read {}: {e}", path.display()))
+}
+
+// A secret comes from its value or from its *_file variant, the
+// command line winning over the config file
+fn secret(
+    value: Option<String>,
+    file: Option<PathBuf>,
+    file_value: Option<String>,
+    file_file: Option<PathBuf>,
+) -> Result<Option<String>, String> {
+    if value.is_some() {
+        return Ok(value);
+    }
+    if let Some(path) = file {
+        return read_secret(&path).map(Some);
+    }
+    if file_value.is_some() {
+        return Ok(file_value);
+    }
+    match file_file {
+        Some(path) => read_secret(&path).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn required(value: Option<String>, name: &str) -> Result<String, String> {
+    value.ok_or(format!(
+        "{name} is missing: use --{}, {} or set it in the config file",
+        name.replace('_', "-"),
+        name.to_uppercase()
+    ))
 }
 
-pub static CONF: Lazy<Arc<Conf>> = Lazy::new(|| {
-    let cfg = Conf::parse();
-    Arc::new(cfg)
-});
+impl Conf {
+    pub fn load() -> Result<Self, String> {
+        let cli = Cli::parse();
+        let file = match &cli.config {
+            Some(path) => Some(Self::read_file(path)?),
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_FILE);
+                if path.exists() {
+                    Some(Self::read_file(&path)?)
+                } else {
+                    None
+                }
+            }
+        };
+        Self::from_layers(cli, file.unwrap_or_default())
+    }
+
+    fn read_file(path: &Path) -> Result<FileConf, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("could not read config file {}: {e}", path.display()))?;
+        toml::from_str(&content).map_err(|e| format!("invalid config file {}: {e}", path.display()))
+    }
+
+    pub fn from_layers(cli: Cli, file: FileConf) -> Result<Self, String> {
+        // a channel given without settings comes on top of the configured ones
+        let mut discord_channels = file.channels;
+        if let Some(id) = cli.discord_channel.or(file.discord_channel) {
+            if !discord_channels.iter().any(|c| c.id == id) {
+                discord_channels.push(ChannelConf {
+                    id,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let mut synology = BTreeMap::new();
+        for (name, nas) in file.synology {
+            let password = secret(None, None, nas.password, nas.password_file)?.ok_or(format!(
+                "synology.{name}: password or password_file is missing"
+            ))?;
+            synology.insert(
+                name,
+                SynologyConf {
+                    root_api: nas.root_api,
+                    user: nas.user,
+                    password,
+                },
+            );
+        }
+        let root_api = cli.synology_root_api.or(file.synology_root_api);
+        let user = cli.synology_user.or(file.synology_user);
+        let password = secret(
+            cli.synology_password,
+            cli.synology_password_file,
+            file.synology_password,
+            file.synology_password_file,
+        )?;
+        // the synology_* settings may be omitted when named backends are set
+        if root_api.is_some() || user.is_some() || password.is_some() || synology.is_empty() {
+            synology.insert(
+                String::from(DEFAULT_NAS),
+                SynologyConf {
+                    root_api: required(root_api, "synology_root_api")?,
+                    user: required(user, "synology_user")?,
+                    password: required(password, "synology_password")?,
+                },
+            );
+        }
+        let default_nas = match file.default_nas {
+            Some(name) => name,
+            None if synology.contains_key(DEFAULT_NAS) => String::from(DEFAULT_NAS),
+            None => synology.keys().next().unwrap().clone(),
+        };
+
+        let conf = Self {
+            command: cli.command,
+            discord_token: secret(
+                cli.discord_token,
+                cli.discord_token_file,
+                file.discord_token,
+                file.discord_token_file,
+            )?,
+            discord_channels,
+            minutes_delta: cli.minutes_delta.or(file.minutes_delta).unwrap_or(2),
+            synology,
+            default_nas,
+            nas: cli.nas,
+            api_listen: cli.api_listen.or(file.api_listen),
+            api_token: secret(
+                cli.api_token,
+                cli.api_token_file,
+                file.api_token,
+                file.api_token_file,
+            )?,
+            discord_public_key: cli.discord_public_key.or(file.discord_public_key),
+            access: file.access,
+            quotas: file.quotas,
+            state_file: cli
+                .state_file
+                .or(file.state_file)
+                .unwrap_or(PathBuf::from(DEFAULT_STATE_FILE)),
+            create_folders: cli.create_folders.or(file.create_folders).unwrap_or(false),
+            remove_finished: cli
+                .remove_finished
+                .or(file.remove_finished)
+                .unwrap_or(false),
+            router: Router::new(
+                file.aliases
+                    .into_iter()
+                    .map(|(alias, path)| (alias.to_lowercase(), path))
+                    .collect(),
+                file.routes,
+            )?,
+            max_download_size: file.max_download_size,
+            hooks: file.hooks,
+            rss: file.rss,
+            organize: file.organize,
+            json: cli.json,
+        };
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.minutes_delta == 0 {
+            return Err(String::from("minutes_delta must be greater than 0"));
+        }
+        for (name, nas) in &self.synology {
+            match Url::parse(&nas.root_api) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                _ => {
+                    return Err(format!(
+                        "synology_root_api must be an http(s) URL (e.g. http://192.168.1.45:5000), got {} for {name}",
+                        nas.root_api
+                    ))
+                }
+            }
+        }
+        let known = |name: &String| self.synology.contains_key(name);
+        if !known(&self.default_nas) {
+            return Err(format!(
+                "default_nas {} is not a configured synology",
+                self.default_nas
+            ));
+        }
+        for channel in &self.discord_channels {
+            if let Some(nas) = channel.nas.as_ref().filter(|nas| !known(nas)) {
+                return Err(format!("channel {}: unknown synology {nas}", channel.id));
+            }
+        }
+        if let Some(key) = &self.discord_public_key {
+            if !hex::decode(key).is_ok_and(|key| key.len() == 32) {
+                return Err(String::from(
+                    "discord_public_key must be the 64 hexadecimal characters shown by the discord developer portal",
+                ));
+            }
+        }
+        for (i, hook) in self.hooks.iter().enumerate() {
+            if hook.command.is_none() && hook.webhook.is_none() && hook.rescan.is_none() {
+                return Err(format!(
+                    "hooks[{i}]: expects a command, a webhook or a rescan"
+                ));
+            }
+        }
+        for (i, feed) in self.rss.iter().enumerate() {
+            FeedFilter::new(&feed.filters, feed.exclude.as_deref())
+                .map_err(|e| format!("rss[{i}]: {e}"))?;
+            if !self.discord_channels.iter().any(|c| c.id == feed.channel) {
+                return Err(format!("rss[{i}]: unknown channel {}", feed.channel));
+            }
+        }
+        // templates are checked against a sample download
+        let sample = Variables::new("Sample.2000.1080p", "video/Movies", "0");
+        for (i, rule) in self.organize.iter().enumerate() {
+            if let Some(path) = &rule.path {
+                organize::render(path, &sample).map_err(|e| format!("organize[{i}]: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Settings of the backend selected with --nas, or of the default one
+    pub fn selected_synology(&self) -> Result<SynologyConf, String> {
+        let name = self.nas.as_ref().unwrap_or(&self.default_nas);
+        self.synology
+            .get(name)
+            .cloned()
+            .ok_or(format!("unknown synology {name}"))
+    }
+
+    pub fn discord(&self) -> Result<DiscordConf, String> {
+        if self.discord_channels.is_empty() {
+            return Err(String::from(
+                "no discord channel: use --discord-channel, DISCORD_CHANNEL or [[channels]] in the config file",
+            ));
+        }
+        Ok(DiscordConf {
+            token: required(self.discord_token.clone(), "discord_token")?,
+            channels: self.discord_channels.clone(),
+            minutes_delta: self.minutes_delta,
+            access: self.access.clone(),
+            router: self.router.clone(),
+            buttons: self.discord_public_key.is_some(),
+            remove_finished: self.remove_finished,
+        })
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::{env, fs};
+
+    use crate::conf::{Cli, Conf, FileConf};
+    use crate::core::units::ByteSize;
+
+    #[test]
+    fn command_line_overrides_file() {
+        let cli = Cli {
+            synology_user: Some("cli_user".to_string()),
+            ..Default::default()
+        };
+        let file: FileConf = toml::from_str(
+            r#"
+            synology_root_api = "http://127.0.0.1:5000"
+            synology_user = "file_user"
+            synology_password = "file_password"
+            minutes_delta = 5
+            create_folders = true
+            "#,
+        )
+        .unwrap();
+        let conf = Conf::from_layers(cli, file).unwrap();
+        assert!(conf.synology["default"].user == "cli_user");
+        assert!(conf.synology["default"].password == "file_password");
+        assert!(conf.minutes_delta == 5);
+        assert!(conf.create_folders);
+
+        // flags set in the file can be turned off
+        let cli = Cli {
+            create_folders: Some(false),
+            ..Default::default()
+        };
+        let file: FileConf = toml::from_str(
+            r#"
+            synology_root_api = "http://127.0.0.1:5000"
+            synology_user = "file_user"
+            synology_password = "file_password"
+            create_folders = true
+            "#,
+        )
+        .unwrap();
+        assert!(!Conf::from_layers(cli, file).unwrap().create_folders);
+    }
+
+    #[test]
+    fn secrets_from_files() {
+        let path = env::temp_dir().join(format!("ds-companion-password-{}", std::process::id()));
+        fs::write(&path, "s3cr3t\n").unwrap();
+        let cli = Cli {
+            synology_root_api: Some("http://127.0.0.1:5000".to_string()),
+            synology_user: Some("user".to_string()),
+            synology_password_file: Some(path.clone()),
+            ..Default::default()
+        };
+        let conf = Conf::from_layers(cli, FileConf::default()).unwrap();
+        assert!(conf.synology["default"].password == "s3cr3t");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_configurations() {
+        let err = Conf::from_layers(Cli::default(), FileConf::default()).unwrap_err();
+        assert!(err.starts_with("synology_root_api is missing"));
+
+        let cli = Cli {
+            synology_root_api: Some("192.168.1.45:5000".to_string()),
+            synology_user: Some("user".to_string()),
+            synology_password: Some("password".to_string()),
+            ..Default::default()
+        };
+        let err = Conf::from_layers(cli, FileConf::default()).unwrap_err();
+        assert!(err.starts_with("synology_root_api must be an http(s) URL"));
+
+        assert!(toml::from_str::<FileConf>("unknown_key = 1").is_err());
+    }
+
+    #[test]
+    fn channels() {
+        let cli = Cli {
+            synology_root_api: Some("http://127.0.0.1:5000".to_string()),
+            synology_user: Some("user".to_string()),
+            synology_password: Some("password".to_string()),
+            discord_token: Some("token".to_string()),
+            discord_channel: Some("3".to_string()),
+            ..Default::default()
+        };
+        let file: FileConf = toml::from_str(
+            r#"
+            [[channels]]
+            id = "1"
+            destination = "video/Movies"
+
+            [[channels]]
+            id = "2"
+            destination = "downloads/iso"
+            allowed_roles = ["42"]
+            "#,
+        )
+        .unwrap();
+        let discord = Conf::from_layers(cli, file).unwrap().discord().unwrap();
+        assert!(discord.channels.len() == 3);
+        assert!(discord.channels[0].destination.as_deref() == Some("video/Movies"));
+        assert!(discord.channels[1].allowed_roles == vec!["42".to_string()]);
+        assert!(discord.channels[2].id == "3");
+        assert!(discord.channels[2].destination.is_none());
+    }
+
+    #[test]
+    fn named_synology_backends() {
+        let file: FileConf = toml::from_str(
+            r#"
+            default_nas = "home-nas"
+
+            [synology.home-nas]
+            root_api = "http://192.168.1.45:5000"
+            user = "user"
+            password = "password"
+
+            [synology.office-nas]
+            root_api = "https://10.0.0.2:5001"
+            user = "user"
+            password = "password"
+
+            [[channels]]
+            id = "1"
+            nas = "office-nas"
+            "#,
+        )
+        .unwrap();
+        let conf = Conf::from_layers(Cli::default(), file).unwrap();
+        assert!(conf.synology.len() == 2);
+        assert!(conf.selected_synology().unwrap().root_api == "http://192.168.1.45:5000");
+
+        let file: FileConf = toml::from_str(
+            r#"
+            [synology.home-nas]
+            root_api = "http://192.168.1.45:5000"
+            user = "user"
+            password = "password"
+
+            [[channels]]
+            id = "1"
+            nas = "office-nas"
+            "#,
+        )
+        .unwrap();
+        let err = Conf::from_layers(Cli::default(), file).unwrap_err();
+        assert!(err == "channel 1: unknown synology office-nas");
+    }
+
+    #[test]
+    fn quotas() {
+        let file: FileConf = toml::from_str(
+            r#"
+            synology_root_api = "http://192.168.1.45:5000"
+            synology_user = "user"
+            synology_password = "password"
+
+            [quotas.default]
+            max_active = 2
+            max_bytes_per_day = "50GB"
+
+            [quotas.users.42]
+            max_active = 5
+            max_bytes_per_week = 1024
+            "#,
+        )
+        .unwrap();
+        let conf = Conf::from_layers(Cli::default(), file).unwrap();
+        let limits = conf.quotas.limits_for("1");
+        assert!(limits.max_active == Some(2));
+        assert!(limits.max_bytes_per_day == Some(ByteSize(50 * 1024 * 1024 * 1024)));
+        let limits = conf.quotas.limits_for("42");
+        assert!(limits.max_active == Some(5));
+        assert!(limits.max_bytes_per_day.is_none());
+        assert!(limits.max_bytes_per_week == Some(ByteSize(1024)));
+
+        let file: Result<FileConf, _> =
+            toml::from_str("[quotas.default]\nmax_bytes_per_day = \"lots\"");
+        assert!(file.is_err());
+    }
+}
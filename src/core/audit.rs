@@ -0,0 +1,46 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use log::{error, info};
+use serde_json::json;
+
+use super::task::Task;
+
+// Who asked for what, and whether it was accepted. Entries are
+// appended as JSON lines to the audit file, or logged if there is none
+#[derive(Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+}
+
+impl AuditLog {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self { path }
+    }
+
+    pub fn record(&self, event: &str, task: &Task, detail: &str) {
+        let entry = json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "event": event,
+            "channel_id": task.channel_id,
+            "message_id": task.message_id,
+            "user_id": task.user_id,
+            "detail": detail,
+        });
+        match &self.path {
+            Some(path) => {
+                let written = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .and_then(|mut file| writeln!(file, "{entry}"));
+                if let Err(e) = written {
+                    error!("Could not write audit log {}: {e}", path.display());
+                }
+            }
+            None => info!("audit: {entry}"),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use log::{info, warn};
+
+use super::task::{RemoteTask, Task};
+use super::traits::DownloadingController;
+
+// Download Station statuses of tasks that are over
+const OVER: [&str; 2] = ["finished", "error"];
+
+// Remove a completed task from the Download Station list, its files
+// are kept
+pub fn remove<T: DownloadingController + ?Sized>(
+    controller: &T,
+    task: &Task,
+    remote: &[RemoteTask],
+) {
+    let remote = remote
+        .iter()
+        .find(|r| task.source.matches(r.uri.as_deref(), Some(&r.title)));
+    match remote {
+        Some(remote) if controller.delete_task(&remote.id) => {
+            info!("Task {} removed from Download Station", task.message_id)
+        }
+        Some(remote) => warn!("Could not remove task {}", remote.id),
+        None => {}
+    }
+}
+
+// Finished or failed tasks, over for more than `days`. Tasks without
+// any known date are kept
+pub fn expired(tasks: &[RemoteTask], days: u64, now: i64) -> Vec<&RemoteTask> {
+    let limit = now - days as i64 * 86400;
+    tasks
+        .iter()
+        .filter(|t| OVER.contains(&t.ds_status.as_str()))
+        .filter(|t| {
+            t.completed_at
+                .or(t.created_at)
+                .is_some_and(|at| at <= limit)
+        })
+        .collect()
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::{
+        cleanup::expired,
+        task::tests::remote,
+        task::{RemoteTask, TaskStatus},
+    };
+
+    fn finished(
+        id: &str,
+        ds_status: &str,
+        created_at: Option<i64>,
+        completed_at: Option<i64>,
+    ) -> RemoteTask {
+        RemoteTask {
+            status: TaskStatus::DONE,
+            ds_status: ds_status.to_string(),
+            created_at,
+            completed_at,
+            ..remote(id, id)
+        }
+    }
+
+    #[test]
+    fn expired_tasks() {
+        let day = 86400;
+        let now = 100 * day;
+        let tasks = [
+            finished("old", "finished", Some(80 * day), Some(85 * day)),
+            finished("recent", "finished", Some(80 * day), Some(95 * day)),
+            finished("failed", "error", Some(85 * day), None),
+            finished("seeding", "seeding", Some(80 * day), Some(85 * day)),
+            finished("undated", "finished", None, None),
+        ];
+        let ids: Vec<&str> = expired(&tasks, 7, now)
+            .iter()
+            .map(|t| t.id.as_str())
+            .collect();
+        assert!(ids == ["old", "failed"]);
+        assert!(expired(&tasks, 30, now).is_empty());
+        assert!(expired(&tasks, 0, now).len() == 3);
+    }
+}
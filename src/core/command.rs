@@ -0,0 +1,111 @@
+// Messages starting with this are commands
+const PREFIX: char = '!';
+
+// A command posted in a chat channel, e.g. "!stats"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatCommand {
+    pub message_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    // lowercase, without the prefix
+    pub name: String,
+    pub args: Vec<String>,
+    // backend of the channel, the default one if None
+    pub nas: Option<String>,
+    // may change the NAS settings
+    pub admin: bool,
+}
+
+impl ChatCommand {
+    // "!speed 2MB 30m" -> ("speed", ["2MB", "30m"]). None for
+    // messages that are not commands
+    pub fn parse(content: &str) -> Option<(String, Vec<String>)> {
+        let mut words = content.trim().strip_prefix(PREFIX)?.split_whitespace();
+        let name = words.next()?.to_lowercase();
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+        Some((name, words.map(String::from).collect()))
+    }
+}
+
+// What the buttons of a status message do to its download
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskAction {
+    PAUSE,
+    RESUME,
+    CANCEL,
+    // cancel, and delete what was downloaded
+    DELETE,
+}
+
+impl TaskAction {
+    pub const ALL: [TaskAction; 4] = [Self::PAUSE, Self::RESUME, Self::CANCEL, Self::DELETE];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::PAUSE => "pause",
+            Self::RESUME => "resume",
+            Self::CANCEL => "cancel",
+            Self::DELETE => "delete",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::PAUSE => "Pause",
+            Self::RESUME => "Resume",
+            Self::CANCEL => "Cancel",
+            Self::DELETE => "Delete files",
+        }
+    }
+
+    pub fn past(&self) -> &'static str {
+        match self {
+            Self::PAUSE => "paused",
+            Self::RESUME => "resumed",
+            Self::CANCEL => "cancelled",
+            Self::DELETE => "deleted with its files",
+        }
+    }
+
+    // Id of the button acting on the download of message_id, e.g.
+    // "pause:1234"
+    pub fn custom_id(&self, message_id: &str) -> String {
+        format!("{}:{message_id}", self.name())
+    }
+
+    pub fn parse(custom_id: &str) -> Option<(Self, String)> {
+        let (name, message_id) = custom_id.split_once(':')?;
+        let action = Self::ALL.into_iter().find(|a| a.name() == name)?;
+        Some((action, message_id.to_string()))
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::command::{ChatCommand, TaskAction};
+
+    #[test]
+    fn command_grammar() {
+        assert!(ChatCommand::parse("!Stats") == Some((String::from("stats"), vec![])));
+        let (name, args) = ChatCommand::parse("  !speed 2MB   30m\n").unwrap();
+        assert!(name == "speed" && args == ["2MB", "30m"]);
+        assert!(ChatCommand::parse("magnet:?xt=urn:btih:aaaa").is_none());
+        assert!(ChatCommand::parse("!").is_none());
+        assert!(ChatCommand::parse("!!!").is_none());
+    }
+
+    #[test]
+    fn button_ids() {
+        for action in TaskAction::ALL {
+            let id = action.custom_id("1234");
+            assert!(TaskAction::parse(&id) == Some((action, String::from("1234"))));
+        }
+        assert!(TaskAction::DELETE.custom_id("1") == "delete:1");
+        assert!(TaskAction::parse("stop:1").is_none());
+        assert!(TaskAction::parse("pause").is_none());
+    }
+}
@@ -0,0 +1,254 @@
+use log::warn;
+use serde::Serialize;
+
+use super::traits::StorageController;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Folder {
+    pub name: String,
+    pub writable: bool,
+}
+
+// Characters DSM refuses in file and folder names
+const FORBIDDEN: [char; 8] = ['\\', ':', '*', '?', '"', '<', '>', '|'];
+
+// The destination given by a user. Paths may be quoted to keep
+// their surrounding spaces
+pub fn parse(value: &str) -> Result<String, String> {
+    unquote(value).and_then(normalize)
+}
+
+pub fn unquote(value: &str) -> Result<&str, String> {
+    let quotes = [('"', '"'), ('\'', '\''), ('“', '”'), ('«', '»')];
+    match quotes.iter().find(|(open, _)| value.starts_with(*open)) {
+        Some((open, close)) => {
+            let inner = &value[open.len_utf8()..];
+            match inner.find(*close) {
+                Some(end) if inner[end + close.len_utf8()..].trim().is_empty() => Ok(&inner[..end]),
+                Some(_) => Err(format!("unexpected text after the quote in {value}")),
+                None => Err(format!("unterminated quote in {value}")),
+            }
+        }
+        None => Ok(value),
+    }
+}
+
+// "/video//Films (2024)/./" -> "video/Films (2024)". Going up
+// with ".." is refused
+pub fn normalize(path: &str) -> Result<String, String> {
+    let mut parts = vec![];
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return Err(format!("{path}: \"..\" is not allowed in destinations")),
+            _ => {
+                if let Some(c) = part
+                    .chars()
+                    .find(|c| FORBIDDEN.contains(c) || c.is_control())
+                {
+                    return Err(format!("{path}: {c:?} is not allowed in destinations"));
+                }
+                parts.push(part);
+            }
+        }
+    }
+    match parts.is_empty() {
+        true => Err(String::from("empty destination")),
+        false => Ok(parts.join("/")),
+    }
+}
+
+// Whether a download in destination is in folder or below. Any
+// download is when there is no folder
+pub fn contains(folder: Option<&str>, destination: Option<&str>) -> bool {
+    let folder = match folder {
+        Some(folder) => folder.trim_matches('/'),
+        None => return true,
+    };
+    destination.is_some_and(|destination| {
+        let destination = destination.trim_matches('/');
+        destination == folder || destination.starts_with(&format!("{folder}/"))
+    })
+}
+
+// Make sure a destination folder exists and is writable before a task
+// is submitted, creating the missing folders if asked to. The error is
+// meant to be shown to the user
+pub fn check<T: StorageController>(
+    storage: &T,
+    destination: &str,
+    create: bool,
+) -> Result<(), String> {
+    let parts: Vec<&str> = destination.split('/').filter(|p| !p.is_empty()).collect();
+    let mut parent = String::new();
+    let mut writable = true;
+    for (i, part) in parts.iter().enumerate() {
+        let folders = match storage.list_folders(&parent) {
+            Some(folders) => folders,
+            None => {
+                // better an unchecked download than no download at all
+                warn!("Could not list {parent}, {destination} is not checked");
+                return Ok(());
+            }
+        };
+        match folders.iter().find(|f| f.name == *part) {
+            Some(folder) => {
+                writable = folder.writable;
+                parent = format!("{parent}/{part}");
+            }
+            // shared folders cannot be created that way
+            None if create && i > 0 => {
+                return match storage.create_folder(&parent, &parts[i..].join("/")) {
+                    true => Ok(()),
+                    false => Err(format!("could not create {destination}")),
+                };
+            }
+            None => {
+                let mut error = format!("{destination} does not exist");
+                if let Some(closest) = closest(part, folders.iter().map(|f| f.name.as_str())) {
+                    let suggestion = [&parts[..i], &[closest], &parts[i + 1..]]
+                        .concat()
+                        .join("/");
+                    error.push_str(&format!(", did you mean {suggestion}?"));
+                }
+                return Err(error);
+            }
+        }
+    }
+    match writable {
+        true => Ok(()),
+        false => Err(format!("{destination} is not writable")),
+    }
+}
+
+// The candidate looking the most like name, if any is close enough
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(2);
+    candidates
+        .map(|c| (distance(&name.to_lowercase(), &c.to_lowercase()), c))
+        .filter(|(d, _)| *d <= max)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+// Levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+
+    use crate::core::{
+        destination::{check, contains, normalize, parse, Folder},
+        organize::Entry,
+        stats::Space,
+        traits::StorageController,
+    };
+
+    struct StorageMock {
+        created: RefCell<Vec<String>>,
+    }
+
+    impl StorageController for StorageMock {
+        fn list_folders(&self, path: &str) -> Option<Vec<Folder>> {
+            let folder = |name: &str, writable| Folder {
+                name: name.to_string(),
+                writable,
+            };
+            match path {
+                "" => Some(vec![folder("video", true), folder("music", false)]),
+                "/video" => Some(vec![folder("Movies", true), folder("Series", true)]),
+                "/music" => Some(vec![]),
+                _ => Some(vec![]),
+            }
+        }
+        fn create_folder(&self, parent: &str, name: &str) -> bool {
+            self.created.borrow_mut().push(format!("{parent}/{name}"));
+            true
+        }
+        fn list_files(&self, _: &str) -> Option<Vec<Entry>> {
+            None
+        }
+        fn move_to(&self, _: &str, _: &str) -> Result<(), String> {
+            panic!("Not implemented")
+        }
+        fn rename(&self, _: &str, _: &str) -> Result<(), String> {
+            panic!("Not implemented")
+        }
+        fn extract(&self, _: &str, _: &str) -> Result<(), String> {
+            panic!("Not implemented")
+        }
+        fn delete(&self, _: &str) -> Result<(), String> {
+            panic!("Not implemented")
+        }
+        fn free_space(&self, _: &str) -> Option<Space> {
+            None
+        }
+    }
+
+    #[test]
+    fn destination_checking() {
+        let storage = StorageMock {
+            created: RefCell::new(vec![]),
+        };
+        assert!(check(&storage, "video/Movies", false).is_ok());
+        assert!(check(&storage, "/video/Series/", false).is_ok());
+        assert!(
+            check(&storage, "video/Movis", false).unwrap_err()
+                == "video/Movis does not exist, did you mean video/Movies?"
+        );
+        assert!(
+            check(&storage, "vidoe/Movies/2024", false).unwrap_err()
+                == "vidoe/Movies/2024 does not exist, did you mean video/Movies/2024?"
+        );
+        assert!(
+            check(&storage, "video/Documentaries", false).unwrap_err()
+                == "video/Documentaries does not exist"
+        );
+        assert!(check(&storage, "music", false).unwrap_err() == "music is not writable");
+
+        assert!(check(&storage, "video/Movies/2024/Summer", true).is_ok());
+        assert!(storage.created.borrow().as_slice() == ["/video/Movies/2024/Summer"]);
+        assert!(check(&storage, "vidoe/Movies", true).is_err());
+    }
+
+    #[test]
+    fn folder_scopes() {
+        assert!(contains(None, None));
+        assert!(contains(Some("video"), Some("video/Movies")));
+        assert!(contains(Some("/video/"), Some("video")));
+        assert!(!contains(Some("video"), Some("videos/Movies")));
+        assert!(!contains(Some("video"), None));
+    }
+
+    #[test]
+    fn destination_grammar() {
+        assert!(parse("Vidéos/Films (2024)").unwrap() == "Vidéos/Films (2024)");
+        assert!(parse("downloads/linux-isos").unwrap() == "downloads/linux-isos");
+        assert!(parse("a.b").unwrap() == "a.b");
+        assert!(parse("\" spaced /folder \"").unwrap() == " spaced /folder ");
+        assert!(parse("“video/Séries”").unwrap() == "video/Séries");
+        assert!(parse("\"video/Movies").is_err());
+        assert!(parse("\"video\"/Movies").is_err());
+
+        assert!(normalize("/video//Movies/./").unwrap() == "video/Movies");
+        assert!(normalize("video/../../etc").is_err());
+        assert!(normalize("video/a:b").is_err());
+        assert!(normalize("/").is_err());
+    }
+}
@@ -0,0 +1,381 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use chrono::Utc;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use super::task::{RemoteTask, Source, Task, TaskStatus};
+
+// Finished entries older than this are forgotten
+const RETENTION_DAYS: i64 = 30;
+
+// What ds-companion knows about the tasks it submitted, kept
+// between runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub message_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub nas: Option<String>,
+    pub uri: Option<String>,
+    // name of the download, to tell uploaded torrents
+    #[serde(default)]
+    pub name: Option<String>,
+    // Source::key, to tell duplicates
+    #[serde(default)]
+    pub key: Option<String>,
+    pub size: u64,
+    pub status: TaskStatus,
+    pub submitted_at: i64,
+    // users who asked for the same download afterwards
+    #[serde(default)]
+    pub subscribers: Vec<String>,
+    // path of the download once organised
+    #[serde(default)]
+    pub location: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn is_active(&self) -> bool {
+        self.status != TaskStatus::DONE && self.status != TaskStatus::FAILED
+    }
+
+    // Whether a task of the downloading backend is this download.
+    // Titles are not unique: only uploaded torrents are told by them
+    pub fn matches(&self, remote: &RemoteTask) -> bool {
+        match self.uri.as_deref() {
+            Some(uri) => Source::from_uri(uri)
+                .is_ok_and(|source| source.matches(remote.uri.as_deref(), None)),
+            None => self.name.as_deref() == Some(remote.title.as_str()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn load(path: PathBuf) -> Self {
+        let entries = Self::read(&path);
+        Self {
+            path: Some(path),
+            entries,
+        }
+    }
+
+    fn read(path: &PathBuf) -> Vec<HistoryEntry> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupted state file {}: {e}", path.display());
+                vec![]
+            }),
+            Err(_) => {
+                debug!("No state file at {}, starting afresh", path.display());
+                vec![]
+            }
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn find(&self, message_id: &str) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|e| e.message_id == message_id)
+    }
+
+    // The active entry of the same download, if any
+    pub fn find_active(&self, key: &str) -> Option<&HistoryEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.is_active() && e.key.as_deref() == Some(key))
+    }
+
+    // The reply to a task already requested or downloading. Its requester
+    // gets mentioned once the download is over
+    pub fn duplicate(&mut self, task: &Task, remote: &[RemoteTask]) -> Option<String> {
+        let remote = remote
+            .iter()
+            .find(|r| task.source.matches(r.uri.as_deref(), Some(&r.title)));
+        let entry = task
+            .source
+            .key()
+            .and_then(|key| self.find_active(&key))
+            .map(|e| (e.message_id.clone(), e.user_id.clone()));
+
+        let requested_by = entry
+            .as_ref()
+            .map(|(_, user)| format!("requested by <@{user}>"));
+        if let Some((message_id, _)) = &entry {
+            self.subscribe(message_id, &task.user_id);
+        }
+        match (remote, requested_by) {
+            (Some(remote), requested_by) => {
+                let state = match remote.status {
+                    TaskStatus::DONE => "already downloaded",
+                    _ => "already downloading",
+                };
+                let details: Vec<String> = requested_by
+                    .into_iter()
+                    .chain([format!("{:.0}%", remote.progress())])
+                    .collect();
+                Some(format!("{state} ({})", details.join(", ")))
+            }
+            (None, Some(requested_by)) => Some(format!("already requested ({requested_by})")),
+            (None, None) => None,
+        }
+    }
+
+    // Active entries of user, which no run follows, take the status of
+    // their task in the backend, and are over once it is gone
+    pub fn refresh(&mut self, user_id: &str, remote: &[RemoteTask]) {
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| e.user_id == user_id && e.is_active())
+        {
+            entry.status = remote
+                .iter()
+                .find(|r| entry.matches(r))
+                .map(|r| r.status)
+                .unwrap_or(TaskStatus::FAILED);
+        }
+    }
+
+    // Mention user as well once the download of message_id is over
+    pub fn subscribe(&mut self, message_id: &str, user_id: &str) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.message_id == message_id) {
+            if entry.user_id != user_id && !entry.subscribers.iter().any(|s| s == user_id) {
+                entry.subscribers.push(user_id.to_string());
+            }
+        }
+    }
+
+    pub fn subscribers(&self, message_id: &str) -> &[String] {
+        self.entries
+            .iter()
+            .find(|e| e.message_id == message_id)
+            .map(|e| e.subscribers.as_slice())
+            .unwrap_or_default()
+    }
+
+    // Insert or update the entry of a task
+    pub fn record(&mut self, task: &Task) {
+        let uri = match &task.source {
+            Source::MAGNET(link) | Source::URL(link) => Some(link.clone()),
+            Source::FILE(_) => None,
+        };
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.message_id == task.message_id)
+        {
+            Some(entry) => {
+                entry.status = task.get_status();
+                entry.size = task.size.unwrap_or(entry.size);
+                if task.location.is_some() {
+                    entry.location = task.location.clone();
+                }
+            }
+            None => self.entries.push(HistoryEntry {
+                message_id: task.message_id.clone(),
+                channel_id: task.channel_id.clone(),
+                user_id: task.user_id.clone(),
+                nas: task.nas.clone(),
+                uri,
+                name: task.source.name(),
+                key: task.source.key(),
+                size: task.size.unwrap_or(0),
+                status: task.get_status(),
+                submitted_at: Utc::now().timestamp(),
+                subscribers: vec![],
+                location: task.location.clone(),
+            }),
+        }
+    }
+
+    // Other runs may have written the file in the meantime: their
+    // entries are kept, ours win but for the subscribers they add
+    pub fn save(&mut self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        // one run at a time reads, merges and writes. Released once
+        // the file is closed
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let _lock = match File::create(&lock_path).and_then(|file| file.lock().map(|()| file)) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Could not lock state file {}: {e}", path.display());
+                return;
+            }
+        };
+        let limit = Utc::now().timestamp() - RETENTION_DAYS * 86400;
+        let mut merged = vec![];
+        for entry in Self::read(path) {
+            match self
+                .entries
+                .iter_mut()
+                .find(|o| o.message_id == entry.message_id)
+            {
+                Some(ours) => {
+                    for user in entry.subscribers {
+                        if !ours.subscribers.contains(&user) {
+                            ours.subscribers.push(user);
+                        }
+                    }
+                }
+                None => merged.push(entry),
+            }
+        }
+        merged.append(&mut self.entries);
+        merged.retain(|e| e.is_active() || e.submitted_at > limit);
+        self.entries = merged;
+
+        // written aside then renamed, so that the file is never half written
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let written = serde_json::to_string(&self.entries)
+            .map_err(|e| e.to_string())
+            .and_then(|content| fs::write(&tmp_path, content).map_err(|e| e.to_string()))
+            .and_then(|()| fs::rename(&tmp_path, path).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Could not write state file {}: {e}", path.display());
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::{env, fs, thread};
+
+    use crate::core::{
+        history::History,
+        notifier::LogNotifier,
+        task::tests::remote as remote_task,
+        task::{RemoteTask, Source, Task},
+    };
+
+    const HEX: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    fn task<'a>(
+        notifier: &'a LogNotifier,
+        message_id: &str,
+        user_id: &str,
+        link: &str,
+    ) -> Task<'a> {
+        Task::new(
+            Source::MAGNET(link.to_string()),
+            message_id.to_string(),
+            String::from("1"),
+            notifier,
+            None,
+            user_id.to_string(),
+        )
+    }
+
+    #[test]
+    fn duplicates() {
+        let notifier = LogNotifier;
+        let mut history = History::default();
+        history.record(&task(
+            &notifier,
+            "1",
+            "alice",
+            &format!("magnet:?xt=urn:btih:{HEX}"),
+        ));
+        let remote = RemoteTask {
+            size: 2048,
+            downloaded: 512,
+            uri: Some(format!(
+                "magnet:?xt=urn:btih:{}&dn=debian",
+                HEX.to_uppercase()
+            )),
+            ..remote_task("dbid_1", "debian")
+        };
+
+        // same torrent, base32 form
+        let repost = task(
+            &notifier,
+            "2",
+            "bob",
+            "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK",
+        );
+        let reply = history
+            .duplicate(&repost, std::slice::from_ref(&remote))
+            .unwrap();
+        assert!(reply == "already downloading (requested by <@alice>, 25%)");
+        assert!(history.subscribers("1") == ["bob"]);
+        let reply = history.duplicate(&repost, &[]).unwrap();
+        assert!(reply == "already requested (requested by <@alice>)");
+        assert!(history.subscribers("1") == ["bob"]);
+
+        // only known by Download Station
+        let mut history = History::default();
+        assert!(
+            history
+                .duplicate(&repost, std::slice::from_ref(&remote))
+                .unwrap()
+                == "already downloading (25%)"
+        );
+        let other = task(
+            &notifier,
+            "3",
+            "bob",
+            &format!("magnet:?xt=urn:btih:{}", "a".repeat(40)),
+        );
+        assert!(history.duplicate(&other, &[remote]).is_none());
+    }
+
+    #[test]
+    fn concurrent_runs() {
+        let path =
+            env::temp_dir().join(format!("ds-companion-history-{}.json", std::process::id()));
+        let notifier = LogNotifier;
+        let mut first = History::load(path.clone());
+        first.record(&task(
+            &notifier,
+            "1",
+            "alice",
+            &format!("magnet:?xt=urn:btih:{HEX}"),
+        ));
+        first.save();
+
+        // another run subscribes bob while the first one follows the task
+        let mut second = History::load(path.clone());
+        second.subscribe("1", "bob");
+        second.save();
+        first.save();
+        assert!(first.subscribers("1") == ["bob"]);
+        assert!(History::load(path.clone()).entries().count() == 1);
+
+        // runs saving at the same time keep each other's entries
+        let runs: Vec<_> = (2..10)
+            .map(|i| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    let notifier = LogNotifier;
+                    let mut history = History::load(path);
+                    let link = format!("https://example.org/{i}.iso");
+                    history.record(&task(&notifier, &i.to_string(), "alice", &link));
+                    history.save();
+                })
+            })
+            .collect();
+        runs.into_iter().for_each(|run| run.join().unwrap());
+        assert!(History::load(path.clone()).entries().count() == 9);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("json.lock"));
+    }
+}
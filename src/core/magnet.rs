@@ -0,0 +1,178 @@
+use std::fmt;
+
+// A parsed magnet URI. Only BitTorrent info-hashes are kept, v1
+// (btih) and/or v2 (btmh), as lowercase hex
+#[derive(Debug, Clone, PartialEq)]
+pub struct Magnet {
+    pub btih: Option<String>,
+    pub btmh: Option<String>,
+    pub name: Option<String>,
+    pub trackers: Vec<String>,
+    pub size: Option<u64>,
+}
+
+impl Magnet {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let query = uri
+            .trim()
+            .strip_prefix("magnet:?")
+            .ok_or(format!("{uri} is not a magnet link"))?;
+        let mut magnet = Magnet {
+            btih: None,
+            btmh: None,
+            name: None,
+            trackers: vec![],
+            size: None,
+        };
+        for (key, value) in query.split('&').filter_map(|p| p.split_once('=')) {
+            let value = decode(value);
+            // parameters may be numbered: xt.1, tr.2...
+            match key.split('.').next().unwrap_or_default() {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        magnet.btih = Some(parse_btih(hash)?);
+                    } else if let Some(hash) = value.strip_prefix("urn:btmh:") {
+                        magnet.btmh = Some(parse_btmh(hash)?);
+                    }
+                }
+                "dn" => magnet.name = Some(value),
+                "tr" if !magnet.trackers.contains(&value) => magnet.trackers.push(value),
+                "xl" => {
+                    magnet.size = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid size xl={value}"))?,
+                    )
+                }
+                _ => {}
+            }
+        }
+        if magnet.btih.is_none() && magnet.btmh.is_none() {
+            return Err(String::from(
+                "the magnet link has no BitTorrent info-hash (xt=urn:btih:...)",
+            ));
+        }
+        Ok(magnet)
+    }
+
+    // Identifies the torrent whatever the magnet looks like, so that
+    // duplicates can be told
+    pub fn key(&self) -> String {
+        match (&self.btih, &self.btmh) {
+            (Some(btih), _) => format!("urn:btih:{btih}"),
+            (None, Some(btmh)) => format!("urn:btmh:{btmh}"),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+// Normalised form of the magnet: hex info-hashes, decoded and
+// deduplicated parameters
+impl fmt::Display for Magnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut params = vec![];
+        if let Some(btih) = &self.btih {
+            params.push(format!("xt=urn:btih:{btih}"));
+        }
+        if let Some(btmh) = &self.btmh {
+            params.push(format!("xt=urn:btmh:{btmh}"));
+        }
+        if let Some(name) = &self.name {
+            params.push(format!("dn={}", urlencoding::encode(name)));
+        }
+        if let Some(size) = self.size {
+            params.push(format!("xl={size}"));
+        }
+        for tracker in &self.trackers {
+            params.push(format!("tr={}", urlencoding::encode(tracker)));
+        }
+        write!(f, "magnet:?{}", params.join("&"))
+    }
+}
+
+fn decode(value: &str) -> String {
+    let value = value.replace('+', " ");
+    urlencoding::decode(&value)
+        .map(|v| v.into_owned())
+        .unwrap_or(value)
+}
+
+// 40 hex digits or 32 base32 characters
+fn parse_btih(hash: &str) -> Result<String, String> {
+    let invalid = || format!("invalid info-hash {hash}");
+    match hash.len() {
+        40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => Ok(hash.to_lowercase()),
+        32 => base32_to_hex(hash).ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+// A multihash: sha2-256 (0x12), 32 bytes long (0x20)
+fn parse_btmh(hash: &str) -> Result<String, String> {
+    match hash.len() == 68
+        && hash.to_lowercase().starts_with("1220")
+        && hash.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        true => Ok(hash.to_lowercase()),
+        false => Err(format!("invalid v2 info-hash {hash}")),
+    }
+}
+
+fn base32_to_hex(hash: &str) -> Option<String> {
+    let mut hex = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in hash.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5 | value) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            hex.push_str(&format!("{:02x}", (buffer >> bits) & 0xff));
+        }
+    }
+    Some(hex)
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::magnet::Magnet;
+
+    const HEX: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    #[test]
+    fn magnet_parsing() {
+        let magnet = Magnet::parse(&format!(
+            "magnet:?xt=urn:btih:{}&dn=debian-12.9.0+amd64.iso&xl=2048&tr=udp%3A%2F%2Ftracker.example.org%3A6969&tr.1=udp%3A%2F%2Ftracker.example.org%3A6969&tr.2=http://other.example.net/announce",
+            HEX.to_uppercase()
+        ))
+        .unwrap();
+        assert!(magnet.btih.as_deref() == Some(HEX));
+        assert!(magnet.name.as_deref() == Some("debian-12.9.0 amd64.iso"));
+        assert!(magnet.size == Some(2048));
+        assert!(magnet.trackers.len() == 2);
+        assert!(magnet.key() == format!("urn:btih:{HEX}"));
+
+        // base32 and hex forms are the same torrent
+        let base32 = Magnet::parse("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK").unwrap();
+        assert!(base32.btih.as_deref() == Some(HEX));
+        assert!(base32.to_string() == format!("magnet:?xt=urn:btih:{HEX}"));
+        assert!(Magnet::parse(&magnet.to_string()).unwrap() == magnet);
+
+        let v2 = format!("1220{}", "ab".repeat(32));
+        let hybrid = Magnet::parse(&format!("magnet:?xt=urn:btmh:{v2}")).unwrap();
+        assert!(hybrid.btih.is_none() && hybrid.key() == format!("urn:btmh:{v2}"));
+
+        assert!(Magnet::parse("magnet:aaaa").is_err());
+        assert!(Magnet::parse("magnet:?dn=nothing").is_err());
+        assert!(Magnet::parse("magnet:?xt=urn:btih:1234").is_err());
+        assert!(Magnet::parse(&format!("magnet:?xt=urn:btih:{}", "z".repeat(40))).is_err());
+        assert!(Magnet::parse("magnet:?xt=urn:btmh:1220abcd").is_err());
+        assert!(Magnet::parse(&format!("magnet:?xt=urn:btih:{HEX}&xl=big")).is_err());
+    }
+}
@@ -1,2 +1,20 @@
+pub mod audit;
+pub mod cleanup;
+pub mod command;
+pub mod destination;
+pub mod history;
+pub mod magnet;
+pub mod notifier;
+pub mod organize;
+pub mod quota;
+pub mod request;
+pub mod routing;
+pub mod rss;
+pub mod search;
+pub mod seeding;
+pub mod speed;
+pub mod stats;
 pub mod task;
+pub mod torrent;
 pub mod traits;
+pub mod units;
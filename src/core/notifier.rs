@@ -0,0 +1,44 @@
+use log::info;
+
+use super::command::ChatCommand;
+use super::search::Search;
+use super::task::Task;
+use super::traits::MessagingController;
+
+// Tasks created outside of a chat (REST API, command line) have
+// no thread to answer in, their progress is only logged
+pub struct LogNotifier;
+
+impl MessagingController for LogNotifier {
+    fn fetch_tasks(&self) -> Option<Vec<Task<'_>>> {
+        Some(vec![])
+    }
+
+    fn update_task_status(&self, task: &mut Task, message: Option<&str>) {
+        info!(
+            "Task {} is now {} {}",
+            task.message_id,
+            task.get_status(),
+            message.unwrap_or_default()
+        );
+    }
+
+    fn fetch_commands(&self) -> Vec<ChatCommand> {
+        vec![]
+    }
+
+    fn reply(&self, command: &ChatCommand, content: &str) {
+        info!("Reply to {}: {content}", command.message_id);
+    }
+
+    fn offer(&self, command: &ChatCommand, search: &Search) {
+        for (i, result) in search.results.iter().enumerate() {
+            info!(
+                "Result {} for {}: {}",
+                i + 1,
+                command.message_id,
+                result.summary()
+            );
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::conf::OrganizeConf;
+
+use super::destination;
+use super::task::{RemoteTask, Task};
+use super::traits::StorageController;
+
+static RE_YEAR: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(19|20)\d{2}\b").unwrap());
+// What usually follows the title in release names
+static RE_RELEASE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(\d{3,4}p|S\d{2}(E\d{2})?|complete|bluray|web-?dl|webrip|hdtv|x26[45]|h\.?26[45])\b")
+        .unwrap()
+});
+static RE_PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{(?<key>[^{}]*)\}").unwrap());
+
+// Extensions of the archives to extract, multi-volume ones from
+// their first part only
+const ARCHIVES: [&str; 7] = ["zip", "rar", "7z", "tar", "gz", "tgz", "bz2"];
+
+// A file or folder of a folder
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+// What a template may be made of
+#[derive(Debug, Default)]
+pub struct Variables {
+    // name of the download
+    pub name: String,
+    pub title: String,
+    pub year: String,
+    // last folder of the destination
+    pub category: String,
+    pub destination: String,
+    pub user: String,
+}
+
+impl Variables {
+    pub fn new(name: &str, destination: &str, user: &str) -> Self {
+        let (title, year) = title_and_year(name);
+        Self {
+            name: name.to_string(),
+            title,
+            year,
+            category: destination
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            destination: destination.to_string(),
+            user: user.to_string(),
+        }
+    }
+}
+
+// "The.Matrix.1999.1080p.BluRay.mkv" -> ("The Matrix", "1999")
+pub fn title_and_year(name: &str) -> (String, String) {
+    let name = match name.rsplit_once('.') {
+        Some((stem, extension)) if extension.len() <= 4 && !extension.contains(' ') => stem,
+        _ => name,
+    };
+    let name = name.replace(['.', '_'], " ");
+    let year = RE_YEAR.find(&name);
+    let end = match (year, RE_RELEASE.find(&name)) {
+        (Some(year), _) => year.start(),
+        (None, Some(release)) => release.start(),
+        (None, None) => name.len(),
+    };
+    let title = name[..end]
+        .trim_end_matches([' ', '-', '(', '['])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let title = match title.is_empty() {
+        true => name.trim().to_string(),
+        false => title,
+    };
+    (
+        title,
+        year.map(|y| y.as_str().to_string()).unwrap_or_default(),
+    )
+}
+
+// "{category}/{title} ({year})" -> "Movies/The Matrix (1999)". Empty
+// parentheses, when the year is unknown, are dropped
+pub fn render(template: &str, variables: &Variables) -> Result<String, String> {
+    let mut unknown = None;
+    let path = RE_PLACEHOLDER.replace_all(template, |c: &regex::Captures| match &c["key"] {
+        "name" => variables.name.clone(),
+        "title" => variables.title.clone(),
+        "year" => variables.year.clone(),
+        "category" => variables.category.clone(),
+        "destination" => variables.destination.clone(),
+        "user" => variables.user.clone(),
+        key => {
+            unknown = Some(key.to_string());
+            String::new()
+        }
+    });
+    if let Some(key) = unknown {
+        return Err(format!("unknown placeholder {{{key}}} in {template}"));
+    }
+    let path = path
+        .replace(" ()", "")
+        .replace("()", "")
+        .split('/')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("/");
+    destination::normalize(&path)
+}
+
+// Archives to extract, "a.part1.rar" but not "a.part2.rar"
+pub fn is_archive(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    let (stem, extension) = match lower.rsplit_once('.') {
+        Some(split) => split,
+        None => return false,
+    };
+    let first_part = match stem.rsplit_once(".part") {
+        Some((_, part)) if part.chars().all(|c| c.is_ascii_digit()) => {
+            part.trim_start_matches('0') == "1"
+        }
+        _ => true,
+    };
+    ARCHIVES.contains(&extension) && first_part
+}
+
+// Extract, flatten, rename then move a finished download. Returns the
+// steps done, to be reported, and where the download ends up
+pub fn organize<T: StorageController + ?Sized>(
+    storage: &T,
+    rule: Option<&OrganizeConf>,
+    task: &Task,
+    remote: &RemoteTask,
+) -> Result<(Vec<String>, String), String> {
+    let destination = remote
+        .destination
+        .clone()
+        .or(task.destination_folder.clone())
+        .ok_or("the destination of the download is unknown")?;
+    let mut parent = format!("/{}", destination.trim_matches('/'));
+    let mut name = remote.title.clone();
+    let is_dir = storage
+        .list_files(&parent)
+        .ok_or(format!("could not list {parent}"))?
+        .iter()
+        .find(|e| e.name == name)
+        .ok_or(format!("{parent}/{name} not found"))?
+        .is_dir;
+    let mut steps = vec![];
+
+    if task.request.extract || rule.is_some_and(|r| r.extract) {
+        let (folder, files) = match is_dir {
+            true => {
+                let folder = format!("{parent}/{name}");
+                let files = storage
+                    .list_files(&folder)
+                    .ok_or(format!("could not list {folder}"))?;
+                let files = files.into_iter().filter(|e| !e.is_dir).map(|e| e.name);
+                (folder, files.collect())
+            }
+            false => (parent.clone(), vec![name.clone()]),
+        };
+        let archives: Vec<&String> = files.iter().filter(|f| is_archive(f)).collect();
+        for archive in &archives {
+            storage.extract(&format!("{folder}/{archive}"), &folder)?;
+        }
+        if !archives.is_empty() {
+            steps.push(format!("extracted {} archive(s)", archives.len()));
+        }
+    }
+
+    let mut is_dir = is_dir;
+    if is_dir && rule.is_some_and(|r| r.flatten) {
+        let folder = format!("{parent}/{name}");
+        let entries = storage
+            .list_files(&folder)
+            .ok_or(format!("could not list {folder}"))?;
+        if let [entry] = entries.as_slice() {
+            if !entry.is_dir {
+                storage.move_to(&format!("{folder}/{}", entry.name), &parent)?;
+                storage.delete(&folder)?;
+                name = entry.name.clone();
+                is_dir = false;
+                steps.push(String::from("flattened"));
+            }
+        }
+    }
+
+    if let Some(new_name) = &task.request.name {
+        // files keep their extension
+        let new_name = match (is_dir, name.rsplit_once('.')) {
+            (false, Some((_, extension))) if !new_name.contains('.') => {
+                format!("{new_name}.{extension}")
+            }
+            _ => new_name.clone(),
+        };
+        if new_name != name {
+            storage.rename(&format!("{parent}/{name}"), &new_name)?;
+            steps.push(format!("renamed to {new_name}"));
+            name = new_name;
+        }
+    }
+
+    if let Some(template) = rule.and_then(|r| r.path.as_ref()) {
+        let variables = Variables::new(&remote.title, &destination, &task.user_id);
+        let target = render(template, &variables)?;
+        let (share, folder) = target.split_once('/').unwrap_or((&target, ""));
+        if !folder.is_empty() && !storage.create_folder(&format!("/{share}"), folder) {
+            return Err(format!("could not create {target}"));
+        }
+        let target = format!("/{target}");
+        if target != parent {
+            storage.move_to(&format!("{parent}/{name}"), &target)?;
+            steps.push(format!("moved to {}", target.trim_start_matches('/')));
+            parent = target;
+        }
+    }
+    log::info!("Task {} organised as {parent}/{name}", task.message_id);
+    Ok((steps, format!("{parent}/{name}")))
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+
+    use crate::{
+        conf::OrganizeConf,
+        core::{
+            destination::Folder,
+            notifier::LogNotifier,
+            organize::{is_archive, organize, render, title_and_year, Entry, Variables},
+            request::Request,
+            stats::Space,
+            task::tests::remote,
+            task::{RemoteTask, Source, Task, TaskStatus},
+            traits::StorageController,
+        },
+    };
+
+    #[derive(Default)]
+    struct StorageMock {
+        actions: RefCell<Vec<String>>,
+    }
+
+    impl StorageController for StorageMock {
+        fn list_folders(&self, _: &str) -> Option<Vec<Folder>> {
+            None
+        }
+        fn create_folder(&self, parent: &str, name: &str) -> bool {
+            self.actions
+                .borrow_mut()
+                .push(format!("create {parent} {name}"));
+            true
+        }
+        fn list_files(&self, path: &str) -> Option<Vec<Entry>> {
+            let entry = |name: &str, is_dir| Entry {
+                name: name.to_string(),
+                is_dir,
+            };
+            match path {
+                "/video/Movies" => Some(vec![
+                    entry("The.Matrix.1999.1080p", true),
+                    entry("Debian.iso", false),
+                    entry("Single", true),
+                ]),
+                "/video/Movies/Single" => Some(vec![entry("single.mkv", false)]),
+                "/video/Movies/The.Matrix.1999.1080p" => Some(vec![
+                    entry("matrix.part1.rar", false),
+                    entry("matrix.part2.rar", false),
+                ]),
+                _ => None,
+            }
+        }
+        fn move_to(&self, path: &str, folder: &str) -> Result<(), String> {
+            self.actions
+                .borrow_mut()
+                .push(format!("move {path} {folder}"));
+            Ok(())
+        }
+        fn rename(&self, path: &str, name: &str) -> Result<(), String> {
+            self.actions
+                .borrow_mut()
+                .push(format!("rename {path} {name}"));
+            Ok(())
+        }
+        fn extract(&self, archive: &str, folder: &str) -> Result<(), String> {
+            self.actions
+                .borrow_mut()
+                .push(format!("extract {archive} {folder}"));
+            Ok(())
+        }
+        fn delete(&self, path: &str) -> Result<(), String> {
+            self.actions.borrow_mut().push(format!("delete {path}"));
+            Ok(())
+        }
+        fn free_space(&self, _: &str) -> Option<Space> {
+            None
+        }
+    }
+
+    fn finished(title: &str) -> RemoteTask {
+        RemoteTask {
+            status: TaskStatus::DONE,
+            ds_status: String::from("finished"),
+            destination: Some(String::from("video/Movies")),
+            ..remote("dbid_1", title)
+        }
+    }
+
+    #[test]
+    fn templates() {
+        assert!(
+            title_and_year("The.Matrix.1999.1080p.BluRay.x264")
+                == ("The Matrix".to_string(), "1999".to_string())
+        );
+        assert!(
+            title_and_year("Some_Show.S01E02.720p.mkv") == ("Some Show".to_string(), String::new())
+        );
+
+        let variables = Variables::new("The.Matrix.1999.1080p", "video/Movies", "42");
+        assert!(
+            render("{destination}/{title} ({year})", &variables).unwrap()
+                == "video/Movies/The Matrix (1999)"
+        );
+        assert!(render("video/{category}/{user}", &variables).unwrap() == "video/Movies/42");
+        let variables = Variables::new("Some.Show.S01E02", "video/Series", "42");
+        assert!(render("video/{title} ({year})/", &variables).unwrap() == "video/Some Show");
+        assert!(render("video/{genre}", &variables).is_err());
+        assert!(render("video/../{title}", &variables).is_err());
+
+        assert!(is_archive("a.zip") && is_archive("a.part1.rar") && is_archive("a.part01.rar"));
+        assert!(!is_archive("a.part2.rar") && !is_archive("a.mkv") && !is_archive("zip"));
+    }
+
+    #[test]
+    fn organisation() {
+        let storage = StorageMock::default();
+        let notifier = LogNotifier;
+        let mut task = Task::new(
+            Source::URL(String::from("https://example.org/matrix")),
+            String::from("1"),
+            String::from("1"),
+            &notifier,
+            None,
+            String::from("42"),
+        );
+        task.request = Request::parse("Extract: yes").unwrap();
+        let rule = OrganizeConf {
+            path: Some(String::from("video/Movies/{title} ({year})")),
+            ..Default::default()
+        };
+        let (steps, path) = organize(
+            &storage,
+            Some(&rule),
+            &task,
+            &finished("The.Matrix.1999.1080p"),
+        )
+        .unwrap();
+        assert!(path == "/video/Movies/The Matrix (1999)/The.Matrix.1999.1080p");
+        assert!(
+            steps
+                == [
+                    "extracted 1 archive(s)",
+                    "moved to video/Movies/The Matrix (1999)"
+                ]
+        );
+        assert!(
+            storage.actions.take()
+                == [
+                    "extract /video/Movies/The.Matrix.1999.1080p/matrix.part1.rar /video/Movies/The.Matrix.1999.1080p",
+                    "create /video Movies/The Matrix (1999)",
+                    "move /video/Movies/The.Matrix.1999.1080p /video/Movies/The Matrix (1999)",
+                ]
+        );
+
+        // single files keep their extension when renamed
+        task.request = Request::parse("Name: Debian 12").unwrap();
+        let (steps, path) = organize(&storage, None, &task, &finished("Debian.iso")).unwrap();
+        assert!(steps == ["renamed to Debian 12.iso"]);
+        assert!(path == "/video/Movies/Debian 12.iso");
+        assert!(storage.actions.take() == ["rename /video/Movies/Debian.iso Debian 12.iso"]);
+
+        let rule = OrganizeConf {
+            flatten: true,
+            ..Default::default()
+        };
+        task.request = Request::default();
+        let (steps, _) = organize(&storage, Some(&rule), &task, &finished("Single")).unwrap();
+        assert!(steps == ["flattened"]);
+        assert!(
+            storage.actions.take()
+                == [
+                    "move /video/Movies/Single/single.mkv /video/Movies",
+                    "delete /video/Movies/Single"
+                ]
+        );
+
+        assert!(organize(&storage, None, &task, &finished("missing")).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::conf::QuotaLimits;
+
+use super::history::History;
+use super::units::human_size;
+
+const DAY: i64 = 86400;
+
+// Check the usage of a user against its limits, before a new
+// task of size bytes (0 if unknown) is submitted. The error tells
+// which limit is reached
+pub fn check(
+    limits: &QuotaLimits,
+    user_id: &str,
+    size: u64,
+    history: &History,
+    now: i64,
+) -> Result<(), String> {
+    let entries: Vec<_> = history.entries().filter(|e| e.user_id == user_id).collect();
+    let since = |seconds: i64| {
+        entries
+            .iter()
+            .filter(move |e| e.submitted_at > now - seconds)
+    };
+
+    if let Some(max) = limits.max_active {
+        // entries left active for more than a week are stale
+        let active = since(7 * DAY).filter(|e| e.is_active()).count();
+        if active >= max {
+            return Err(format!("{active} active downloads (max {max})"));
+        }
+    }
+    if let Some(max) = limits.max_tasks_per_day {
+        let count = since(DAY).count();
+        if count >= max {
+            return Err(format!("{count} downloads today (max {max})"));
+        }
+    }
+    if let Some(max) = limits.max_bytes_per_day {
+        let bytes: u64 = since(DAY).map(|e| e.size).sum();
+        bytes_limit(bytes, size, max.0, "today")?;
+    }
+    if let Some(max) = limits.max_bytes_per_week {
+        let bytes: u64 = since(7 * DAY).map(|e| e.size).sum();
+        bytes_limit(bytes, size, max.0, "this week")?;
+    }
+    Ok(())
+}
+
+// Already reached, or would be by the new download
+fn bytes_limit(used: u64, size: u64, max: u64, period: &str) -> Result<(), String> {
+    if used >= max {
+        return Err(format!(
+            "{} downloaded {period} (max {})",
+            human_size(used),
+            human_size(max)
+        ));
+    }
+    if used.saturating_add(size) > max {
+        return Err(format!(
+            "{} more after {} downloaded {period} (max {})",
+            human_size(size),
+            human_size(used),
+            human_size(max)
+        ));
+    }
+    Ok(())
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        conf::QuotaLimits,
+        core::{
+            history::History,
+            notifier::LogNotifier,
+            quota::check,
+            task::{Source, Task},
+            units::ByteSize,
+        },
+    };
+
+    #[test]
+    fn limits_enforcement() {
+        let notifier = LogNotifier;
+        let mut history = History::default();
+        for id in ["1", "2"] {
+            let mut task = Task::new(
+                Source::MAGNET(String::from_str("magnet:?xt9420FA29A").unwrap()),
+                String::from_str(id).unwrap(),
+                String::from_str("1").unwrap(),
+                &notifier,
+                None,
+                String::from_str("42").unwrap(),
+            );
+            task.size = Some(1536);
+            history.record(&task);
+        }
+        let now = chrono::Utc::now().timestamp();
+
+        assert!(check(&QuotaLimits::default(), "42", 0, &history, now).is_ok());
+        let limits = QuotaLimits {
+            max_active: Some(2),
+            ..Default::default()
+        };
+        assert!(
+            check(&limits, "42", 0, &history, now).unwrap_err() == "2 active downloads (max 2)"
+        );
+        assert!(check(&limits, "7", 0, &history, now).is_ok());
+        let limits = QuotaLimits {
+            max_bytes_per_day: Some(ByteSize(2048)),
+            ..Default::default()
+        };
+        assert!(
+            check(&limits, "42", 0, &history, now).unwrap_err()
+                == "3.0 KB downloaded today (max 2.0 KB)"
+        );
+        // a week later, nothing counts anymore
+        assert!(check(&limits, "42", 0, &history, now + 8 * 86400).is_ok());
+        // a single download bigger than what is left
+        let limits = QuotaLimits {
+            max_bytes_per_week: Some(ByteSize(4096)),
+            ..Default::default()
+        };
+        assert!(check(&limits, "42", 1024, &history, now).is_ok());
+        assert!(
+            check(&limits, "42", 2048, &history, now).unwrap_err()
+                == "2.0 KB more after 3.0 KB downloaded this week (max 4.0 KB)"
+        );
+        assert!(check(&limits, "7", 8192, &history, now).is_err());
+    }
+}
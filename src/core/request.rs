@@ -0,0 +1,279 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use super::destination;
+use super::seeding::SeedPolicy;
+
+static RE_OPTION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?<indent>[ \t]*)(?<key>[A-Za-z]+)[ \t]*:[ \t]*(?<value>.*?)[ \t]*$").unwrap()
+});
+static RE_MENTION: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<@!?(?<id>\d+)>|\b(?<raw>\d{5,})\b").unwrap());
+
+// Lines starting with these are links, not options
+const SCHEMES: [&str; 4] = ["magnet", "http", "https", "ftp"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Serialize)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+// Files of a torrent, by 1-based index or by glob pattern
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct FileSelection {
+    pub indexes: Vec<usize>,
+    pub patterns: Vec<String>,
+}
+
+impl FileSelection {
+    // 0-based indexes of the selected files among the torrent's ones
+    pub fn select(&self, files: &[String]) -> Vec<usize> {
+        files
+            .iter()
+            .enumerate()
+            .filter(|(i, file)| {
+                let name = file.rsplit('/').next().unwrap_or(file);
+                self.indexes.contains(&(i + 1))
+                    || self
+                        .patterns
+                        .iter()
+                        .any(|p| glob_match(p, file) || glob_match(p, name))
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+// Case insensitive glob matching, with * and ?
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // last * seen, and where the text was then
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// What a user asked for along with a download, one "Key: value" line
+// per option
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Request {
+    // To:
+    pub destination: Option<String>,
+    // On:
+    pub nas: Option<String>,
+    pub priority: Priority,
+    // name to give to the download once done
+    pub name: Option<String>,
+    // files to download, all of them if None
+    pub files: Option<FileSelection>,
+    // when to stop seeding, as DSM is set if None
+    pub seed: Option<SeedPolicy>,
+    // users to mention once the download is over
+    pub notify: Vec<String>,
+    pub extract: bool,
+    // keys nobody knows about, reported to the user
+    pub unknown: Vec<String>,
+}
+
+impl Request {
+    // Parse the options of a message. An option with an invalid
+    // value makes the whole message invalid
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut request = Self::default();
+        for line in content.lines() {
+            let captures = match RE_OPTION.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            let key = captures["key"].to_lowercase();
+            let value = &captures["value"];
+            match key.as_str() {
+                "to" => request.destination = Some(destination::parse(value)?),
+                "on" => request.nas = Some(single_word(value, "On")?),
+                "priority" => request.priority = parse_priority(value)?,
+                "name" => {
+                    let name = destination::unquote(value)?;
+                    if name.is_empty() || name.contains('/') {
+                        return Err(format!("invalid name {value}"));
+                    }
+                    request.name = Some(name.to_string());
+                }
+                "files" => request.files = Some(parse_files(value)?),
+                "seed" => request.seed = Some(SeedPolicy::parse(value)?),
+                "notify" => request.notify = parse_mentions(value)?,
+                "extract" => request.extract = parse_bool(value, "Extract")?,
+                key if SCHEMES.contains(&key) => {}
+                // only lines starting with a key are options, others
+                // are part of the message
+                _ if !captures["indent"].is_empty() => {}
+                _ => request.unknown.push(captures["key"].to_string()),
+            }
+        }
+        Ok(request)
+    }
+}
+
+fn single_word(value: &str, key: &str) -> Result<String, String> {
+    match value.split_whitespace().count() {
+        1 => Ok(value.to_string()),
+        _ => Err(format!("{key}: expects a single word, got {value:?}")),
+    }
+}
+
+fn parse_priority(value: &str) -> Result<Priority, String> {
+    match value.to_lowercase().as_str() {
+        "low" => Ok(Priority::Low),
+        "normal" | "" => Ok(Priority::Normal),
+        "high" => Ok(Priority::High),
+        _ => Err(format!(
+            "Priority: expects low, normal or high, got {value:?}"
+        )),
+    }
+}
+
+// "1,3-5,*.srt" -> [1, 3, 4, 5] and ["*.srt"]
+fn parse_files(value: &str) -> Result<FileSelection, String> {
+    let invalid =
+        || format!("Files: expects indexes or patterns such as 1,3-5,*.srt, got {value:?}");
+    let mut selection = FileSelection::default();
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if !part
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '-' || c.is_whitespace())
+        {
+            selection.patterns.push(part.to_string());
+            continue;
+        }
+        let (start, end) = part.split_once('-').unwrap_or((part, part));
+        let start: usize = start.trim().parse().map_err(|_| invalid())?;
+        let end: usize = end.trim().parse().map_err(|_| invalid())?;
+        if start == 0 || end < start {
+            return Err(invalid());
+        }
+        selection.indexes.extend(start..=end);
+    }
+    if selection.indexes.is_empty() && selection.patterns.is_empty() {
+        return Err(invalid());
+    }
+    selection.indexes.sort_unstable();
+    selection.indexes.dedup();
+    Ok(selection)
+}
+
+// Discord mentions (<@id>) or raw user ids
+fn parse_mentions(value: &str) -> Result<Vec<String>, String> {
+    let users: Vec<String> = RE_MENTION
+        .captures_iter(value)
+        .filter_map(|c| c.name("id").or(c.name("raw")))
+        .map(|id| id.as_str().to_string())
+        .collect();
+    match users.is_empty() {
+        true => Err(format!("Notify: expects @mentions, got {value:?}")),
+        false => Ok(users),
+    }
+}
+
+fn parse_bool(value: &str, key: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" | "y" | "true" | "on" | "1" | "" => Ok(true),
+        "no" | "n" | "false" | "off" | "0" => Ok(false),
+        _ => Err(format!("{key}: expects yes or no, got {value:?}")),
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::request::{FileSelection, Priority, Request};
+
+    #[test]
+    fn message_grammar() {
+        let request = Request::parse(
+            "magnet:?xt=urn:btih:aaaa&dn=debian\n\
+             Have a look at this one\n\
+             TO : Vidéos/Films (2024)\n\
+             On: office-nas\n\
+             priority: HIGH\n\
+             Name: \"Debian 12\"\n\
+             Files: 4, 1-2,2\n\
+             Seed: 2\n\
+             Notify: <@123456789> and <@!987654321>\n\
+             Extract: yes\n\
+             Colour: blue\n\
+             \tNote: not an option\n\
+             Any idea why: it failed?",
+        )
+        .unwrap();
+        assert!(request.destination.unwrap() == "Vidéos/Films (2024)");
+        assert!(request.nas.unwrap() == "office-nas");
+        assert!(request.priority == Priority::High);
+        assert!(request.name.unwrap() == "Debian 12");
+        assert!(request.files.unwrap().indexes == [1, 2, 4]);
+        assert!(request.seed.unwrap().ratio == Some(2.0));
+        assert!(request.notify == ["123456789", "987654321"]);
+        assert!(request.extract);
+        assert!(request.unknown == ["Colour"]);
+
+        let request = Request::parse("magnet:?xt=aaa\n|o: video\nPhoto: video").unwrap();
+        assert!(request.destination.is_none());
+        assert!(request.unknown == ["Photo"]);
+        assert!(Request::parse("magnet:?xt=aaa").unwrap() == Request::default());
+
+        assert!(Request::parse("To: video/../etc").is_err());
+        assert!(Request::parse("Files: 3-1").is_err());
+        assert!(Request::parse("Files: 0").is_err());
+        assert!(Request::parse("Seed: lots").is_err());
+        assert!(Request::parse("Priority: urgent").is_err());
+        assert!(Request::parse("Notify: nobody").is_err());
+        assert!(Request::parse("Extract: maybe").is_err());
+        assert!(Request::parse("Name: a/b").is_err());
+        assert!(Request::parse("Name: \"Debian\" 12").is_err());
+    }
+
+    #[test]
+    fn file_selection() {
+        let files: Vec<String> = [
+            "Show/S01E01.mkv",
+            "Show/S01E01.en.srt",
+            "Show/S01E01.fr.SRT",
+            "Show/sample.mkv",
+        ]
+        .map(String::from)
+        .to_vec();
+        let selection = Request::parse("Files: 1, *.fr.srt").unwrap().files.unwrap();
+        assert!(selection.select(&files) == [0, 2]);
+        let selection = FileSelection {
+            patterns: vec![String::from("*.srt"), String::from("Show/s?mple*")],
+            ..Default::default()
+        };
+        assert!(selection.select(&files) == [1, 2, 3]);
+        let selection = Request::parse("Files: 9, *.iso").unwrap().files.unwrap();
+        assert!(selection.select(&files).is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use crate::conf::RouteConf;
+
+use super::task::Source;
+
+// What can be told about a download before submitting it
+#[derive(Debug, Default)]
+pub struct SourceInfo {
+    pub name: Option<String>,
+    pub trackers: Vec<String>,
+    pub files: Vec<String>,
+}
+
+impl SourceInfo {
+    pub fn from_source(source: &Source) -> Self {
+        match source {
+            Source::MAGNET(_) => match source.magnet() {
+                Some(magnet) => Self {
+                    name: magnet.name,
+                    trackers: magnet.trackers,
+                    ..Default::default()
+                },
+                None => Self::default(),
+            },
+            Source::FILE(_) => match source.torrent() {
+                Some(torrent) => Self {
+                    name: Some(torrent.name),
+                    trackers: torrent.trackers,
+                    files: torrent.files.into_iter().map(|file| file.path).collect(),
+                },
+                None => Self::default(),
+            },
+            Source::URL(_) => Self {
+                name: source.name(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    pattern: Option<Regex>,
+    tracker: Option<String>,
+    extensions: Vec<String>,
+    destination: String,
+}
+
+impl Route {
+    // Every criterion given must match
+    fn matches(&self, info: &SourceInfo) -> bool {
+        let name = info.name.as_deref().unwrap_or_default();
+        let pattern = match &self.pattern {
+            Some(pattern) => pattern.is_match(name),
+            None => true,
+        };
+        let tracker = match &self.tracker {
+            Some(tracker) => info.trackers.iter().any(|t| t.contains(tracker.as_str())),
+            None => true,
+        };
+        let extensions = self.extensions.is_empty()
+            || std::iter::once(name)
+                .chain(info.files.iter().map(String::as_str))
+                .any(|file| {
+                    let file = file.to_lowercase();
+                    self.extensions.iter().any(|ext| {
+                        file.ends_with(&format!(".{}", ext.trim_start_matches('.').to_lowercase()))
+                    })
+                });
+        pattern && tracker && extensions
+    }
+}
+
+// Destination aliases and the rules choosing a destination when a
+// message does not give any
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    aliases: BTreeMap<String, String>,
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new(aliases: BTreeMap<String, String>, routes: Vec<RouteConf>) -> Result<Self, String> {
+        let routes = routes
+            .into_iter()
+            .map(|route| {
+                let pattern = match route.pattern {
+                    Some(pattern) => Some(
+                        Regex::new(&pattern)
+                            .map_err(|e| format!("invalid route pattern {pattern}: {e}"))?,
+                    ),
+                    None => None,
+                };
+                Ok(Route {
+                    pattern,
+                    tracker: route.tracker,
+                    extensions: route.extensions,
+                    destination: route.destination,
+                })
+            })
+            .collect::<Result<Vec<Route>, String>>()?;
+        Ok(Self { aliases, routes })
+    }
+
+    // "movies" -> "video/Movies", other destinations are kept as is
+    pub fn resolve(&self, destination: &str) -> String {
+        self.aliases
+            .get(&destination.to_lowercase())
+            .cloned()
+            .unwrap_or(destination.to_string())
+    }
+
+    // Destination of the first rule matching the source
+    pub fn route(&self, source: &Source) -> Option<String> {
+        let info = SourceInfo::from_source(source);
+        self.routes
+            .iter()
+            .find(|route| route.matches(&info))
+            .map(|route| self.resolve(&route.destination))
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+
+    use bytes::Bytes;
+
+    use crate::{
+        conf::RouteConf,
+        core::{routing::Router, task::Source, torrent::tests::torrent_file},
+    };
+
+    #[test]
+    fn aliases_and_routes() {
+        let aliases = BTreeMap::from([
+            (String::from("movies"), String::from("video/Movies")),
+            (String::from("series"), String::from("video/Series")),
+        ]);
+        let routes = vec![
+            RouteConf {
+                pattern: Some(String::from(r"S\d\dE\d\d")),
+                destination: String::from("series"),
+                ..Default::default()
+            },
+            RouteConf {
+                tracker: Some(String::from("linuxtracker.org")),
+                destination: String::from("downloads/iso"),
+                ..Default::default()
+            },
+            RouteConf {
+                extensions: vec![String::from("mkv")],
+                destination: String::from("movies"),
+                ..Default::default()
+            },
+        ];
+        let router = Router::new(aliases, routes).unwrap();
+        assert!(router.resolve("Movies") == "video/Movies");
+        assert!(router.resolve("video/Other") == "video/Other");
+
+        let magnet = |query: &str| {
+            Source::MAGNET(format!(
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a&{query}"
+            ))
+        };
+        assert!(router.route(&magnet("dn=Some.Show.S01E02.720p")).unwrap() == "video/Series");
+        assert!(
+            router
+                .route(&magnet(
+                    "dn=debian.iso&tr=udp%3A%2F%2Flinuxtracker.org%3A2710"
+                ))
+                .unwrap()
+                == "downloads/iso"
+        );
+        assert!(router.route(&magnet("dn=Some+Movie+(2024).MKV")).unwrap() == "video/Movies");
+        assert!(router.route(&magnet("dn=something")).is_none());
+        assert!(
+            router
+                .route(&Source::URL(String::from(
+                    "https://example.org/files/film.mkv?token=1"
+                )))
+                .unwrap()
+                == "video/Movies"
+        );
+
+        let invalid = vec![RouteConf {
+            pattern: Some(String::from("S(")),
+            ..Default::default()
+        }];
+        assert!(Router::new(BTreeMap::new(), invalid).is_err());
+    }
+
+    #[test]
+    fn uploaded_torrents() {
+        let route = |tracker: Option<&str>, extension: &str, destination: &str| RouteConf {
+            tracker: tracker.map(String::from),
+            extensions: vec![String::from(extension)],
+            destination: String::from(destination),
+            ..Default::default()
+        };
+        let torrent = Source::FILE(Bytes::from(torrent_file()));
+        // by the files and trackers of the torrent, not only its name
+        let routes = vec![
+            route(None, "mkv", "video/Movies"),
+            route(Some("other.example.net"), "iso", "downloads/iso"),
+        ];
+        let router = Router::new(BTreeMap::new(), routes).unwrap();
+        assert!(router.route(&torrent).unwrap() == "downloads/iso");
+        let routes = vec![route(Some("linuxtracker.org"), "iso", "downloads/iso")];
+        let router = Router::new(BTreeMap::new(), routes).unwrap();
+        assert!(router.route(&torrent).is_none());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::DateTime;
+use log::{debug, error, warn};
+use regex::Regex;
+use roxmltree::{Document, Node};
+
+use super::units::human_size;
+
+// Ids remembered per feed, older ones are forgotten
+const MAX_SEEN: usize = 1000;
+
+// An RSS site subscribed in Download Station
+#[derive(Debug, Clone, PartialEq)]
+pub struct RssSite {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    pub updated_at: Option<i64>,
+}
+
+impl fmt::Display for RssSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {} ({})", self.id, self.title, self.url)?;
+        if let Some(updated) = self.updated_at.and_then(|t| DateTime::from_timestamp(t, 0)) {
+            write!(f, ", updated {}", updated.format("%Y-%m-%d %H:%M UTC"))?;
+        }
+        Ok(())
+    }
+}
+
+// An item of an RSS site of Download Station
+#[derive(Debug, Clone, PartialEq)]
+pub struct RssItem {
+    pub title: String,
+    pub size: Option<u64>,
+    pub published: Option<i64>,
+}
+
+impl fmt::Display for RssItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if let Some(size) = self.size {
+            write!(f, ", {}", human_size(size))?;
+        }
+        if let Some(published) = self.published.and_then(|t| DateTime::from_timestamp(t, 0)) {
+            write!(f, ", {}", published.format("%Y-%m-%d"))?;
+        }
+        Ok(())
+    }
+}
+
+// An item of a feed watched by ds-companion itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    // guid, or the link when there is none
+    pub id: String,
+    pub title: String,
+    // the enclosure (torrent or magnet) if any, else the link
+    pub link: String,
+}
+
+fn child<'a>(node: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+// Items of an RSS 2.0 or Atom document, in the document order
+pub fn parse_feed(xml: &str) -> Result<Vec<FeedItem>, String> {
+    let document = Document::parse(xml).map_err(|e| format!("invalid feed: {e}"))?;
+    let root = document.root_element();
+    let items = match root.tag_name().name() {
+        "rss" => child(root, "channel")
+            .ok_or("invalid feed: no channel")?
+            .children()
+            .filter(|n| n.tag_name().name() == "item")
+            .filter_map(|item| {
+                let enclosure = child(item, "enclosure")
+                    .and_then(|e| e.attribute("url"))
+                    .map(String::from);
+                // torznab feeds give magnets in an attribute
+                let magnet = item
+                    .children()
+                    .find(|n| n.attribute("name") == Some("magneturl"))
+                    .and_then(|n| n.attribute("value"))
+                    .map(String::from);
+                let link = magnet.or(enclosure).or(text(item, "link"))?;
+                Some(FeedItem {
+                    id: text(item, "guid").unwrap_or(link.clone()),
+                    title: text(item, "title").unwrap_or(link.clone()),
+                    link,
+                })
+            })
+            .collect(),
+        "feed" => root
+            .children()
+            .filter(|n| n.tag_name().name() == "entry")
+            .filter_map(|entry| {
+                let links: Vec<Node> = entry
+                    .children()
+                    .filter(|n| n.tag_name().name() == "link")
+                    .collect();
+                let link = links
+                    .iter()
+                    .find(|l| l.attribute("rel") == Some("enclosure"))
+                    .or(links.first())
+                    .and_then(|l| l.attribute("href"))
+                    .map(String::from)?;
+                Some(FeedItem {
+                    id: text(entry, "id").unwrap_or(link.clone()),
+                    title: text(entry, "title").unwrap_or(link.clone()),
+                    link,
+                })
+            })
+            .collect(),
+        other => return Err(format!("invalid feed: unexpected <{other}>")),
+    };
+    Ok(items)
+}
+
+// Titles worth downloading: matching one of the filters (any title
+// when there are none) but not the exclusion
+#[derive(Debug, Clone)]
+pub struct FeedFilter {
+    filters: Vec<Regex>,
+    exclude: Option<Regex>,
+}
+
+impl FeedFilter {
+    pub fn new(filters: &[String], exclude: Option<&str>) -> Result<Self, String> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| format!("invalid pattern {pattern:?}: {e}"))
+        };
+        Ok(Self {
+            filters: filters
+                .iter()
+                .map(|f| compile(f))
+                .collect::<Result<_, _>>()?,
+            exclude: exclude.map(compile).transpose()?,
+        })
+    }
+
+    pub fn matches(&self, title: &str) -> bool {
+        (self.filters.is_empty() || self.filters.iter().any(|f| f.is_match(title)))
+            && !self.exclude.as_ref().is_some_and(|e| e.is_match(title))
+    }
+}
+
+// Ids of the items already seen in each feed, kept between runs
+#[derive(Default)]
+pub struct Seen {
+    path: Option<PathBuf>,
+    feeds: BTreeMap<String, Vec<String>>,
+}
+
+impl Seen {
+    pub fn load(path: PathBuf) -> Self {
+        let feeds = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupted RSS state file {}: {e}", path.display());
+                BTreeMap::new()
+            }),
+            Err(_) => {
+                debug!("No RSS state file at {}", path.display());
+                BTreeMap::new()
+            }
+        };
+        Self {
+            path: Some(path),
+            feeds,
+        }
+    }
+
+    // Items not seen before, which are now. Nothing is new in a feed
+    // never seen: its backlog is not downloaded
+    pub fn unseen(&mut self, url: &str, items: Vec<FeedItem>) -> Vec<FeedItem> {
+        let first_time = !self.feeds.contains_key(url);
+        let seen = self.feeds.entry(url.to_string()).or_default();
+        let mut new = vec![];
+        for item in items {
+            if !seen.contains(&item.id) {
+                seen.push(item.id.clone());
+                new.push(item);
+            }
+        }
+        if seen.len() > MAX_SEEN {
+            seen.drain(..seen.len() - MAX_SEEN);
+        }
+        match first_time {
+            true => vec![],
+            false => new,
+        }
+    }
+
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let written = serde_json::to_string(&self.feeds)
+            .map_err(|e| e.to_string())
+            .and_then(|content| fs::write(path, content).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Could not write RSS state file {}: {e}", path.display());
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::rss::{parse_feed, FeedFilter, FeedItem, Seen};
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:torznab="http://torznab.com/schemas/2015/feed">
+  <channel>
+    <title>Linux</title>
+    <item>
+      <title>debian-12.5.0-amd64-netinst.iso</title>
+      <guid>deb-12.5</guid>
+      <link>https://example.org/debian</link>
+      <enclosure url="https://example.org/debian.torrent" type="application/x-bittorrent"/>
+    </item>
+    <item>
+      <title>Ubuntu 24.04</title>
+      <link>https://example.org/ubuntu</link>
+      <torznab:attr name="magneturl" value="magnet:?xt=urn:btih:aaaa"/>
+    </item>
+    <item><title>no link</title></item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Releases</title>
+  <entry>
+    <title>Fedora 40</title>
+    <id>urn:fedora:40</id>
+    <link href="https://example.org/fedora"/>
+    <link rel="enclosure" href="https://example.org/fedora.torrent"/>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn feed_parsing() {
+        let items = parse_feed(RSS).unwrap();
+        assert!(items.len() == 2);
+        assert!(
+            items[0]
+                == FeedItem {
+                    id: String::from("deb-12.5"),
+                    title: String::from("debian-12.5.0-amd64-netinst.iso"),
+                    link: String::from("https://example.org/debian.torrent"),
+                }
+        );
+        assert!(items[1].link == "magnet:?xt=urn:btih:aaaa");
+        assert!(items[1].id == items[1].link);
+
+        let items = parse_feed(ATOM).unwrap();
+        assert!(items[0].id == "urn:fedora:40");
+        assert!(items[0].link == "https://example.org/fedora.torrent");
+
+        assert!(parse_feed("<html></html>").is_err());
+        assert!(parse_feed("not xml").is_err());
+    }
+
+    #[test]
+    fn feed_filters() {
+        let filter = FeedFilter::new(
+            &[String::from("(?i)debian.*amd64"), String::from("^Ubuntu")],
+            Some("(?i)netinst"),
+        )
+        .unwrap();
+        assert!(filter.matches("debian-12.5.0-amd64-DVD-1.iso"));
+        assert!(!filter.matches("debian-12.5.0-amd64-netinst.iso"));
+        assert!(filter.matches("Ubuntu 24.04"));
+        assert!(!filter.matches("Fedora 40"));
+        assert!(FeedFilter::new(&[], None).unwrap().matches("Fedora 40"));
+        assert!(FeedFilter::new(&[String::from("(")], None).is_err());
+    }
+
+    #[test]
+    fn seen_items() {
+        let mut seen = Seen::default();
+        let items = parse_feed(RSS).unwrap();
+        // the backlog of a new feed is skipped
+        assert!(seen
+            .unseen("https://example.org/rss", items.clone())
+            .is_empty());
+        assert!(seen
+            .unseen("https://example.org/rss", items.clone())
+            .is_empty());
+        let mut more = parse_feed(ATOM).unwrap();
+        more.extend(items);
+        let new = seen.unseen("https://example.org/rss", more);
+        assert!(new.len() == 1 && new[0].title == "Fedora 40");
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::PathBuf;
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use super::units::human_size;
+
+// Searches older than this cannot be picked from anymore
+const RETENTION_SECONDS: i64 = 86400;
+
+// A torrent found by Download Station's BT search
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub uri: String,
+    pub size: u64,
+    pub seeds: u64,
+    pub leechs: u64,
+    pub provider: String,
+}
+
+impl SearchResult {
+    // "debian.iso, 3.7 GB, 120 seeders (linuxtracker)"
+    pub fn summary(&self) -> String {
+        format!(
+            "{}, {}, {} seeders ({})",
+            self.title,
+            human_size(self.size),
+            self.seeds,
+            self.provider
+        )
+    }
+}
+
+// The results offered to a user, who picks one of them afterwards
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Search {
+    // the !search message
+    pub message_id: String,
+    pub channel_id: String,
+    pub user_id: String,
+    pub terms: String,
+    pub results: Vec<SearchResult>,
+    pub searched_at: i64,
+}
+
+impl Search {
+    // Result number n, counting from 1 as they are shown
+    pub fn pick(&self, n: usize) -> Option<&SearchResult> {
+        n.checked_sub(1).and_then(|i| self.results.get(i))
+    }
+}
+
+// The latest search of each user in each channel, kept between runs
+#[derive(Default)]
+pub struct Searches {
+    path: Option<PathBuf>,
+    searches: Vec<Search>,
+}
+
+impl Searches {
+    pub fn load(path: PathBuf) -> Self {
+        let searches = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupted search file {}: {e}", path.display());
+                vec![]
+            }),
+            Err(_) => {
+                debug!("No search file at {}", path.display());
+                vec![]
+            }
+        };
+        Self {
+            path: Some(path),
+            searches,
+        }
+    }
+
+    // A new search replaces the previous one of the user in the channel
+    pub fn record(&mut self, search: Search) {
+        self.searches
+            .retain(|s| s.channel_id != search.channel_id || s.user_id != search.user_id);
+        self.searches.push(search);
+    }
+
+    pub fn last(&self, channel_id: &str, user_id: &str, now: i64) -> Option<&Search> {
+        self.searches.iter().find(|s| {
+            s.channel_id == channel_id
+                && s.user_id == user_id
+                && s.searched_at > now - RETENTION_SECONDS
+        })
+    }
+
+    pub fn save(&mut self, now: i64) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        self.searches
+            .retain(|s| s.searched_at > now - RETENTION_SECONDS);
+        if self.searches.is_empty() && !path.exists() {
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let written = serde_json::to_string(&self.searches)
+            .map_err(|e| e.to_string())
+            .and_then(|content| fs::write(path, content).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Could not write search file {}: {e}", path.display());
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::search::{Search, SearchResult, Searches};
+
+    pub fn result(title: &str, seeds: u64) -> SearchResult {
+        SearchResult {
+            title: title.to_string(),
+            uri: format!("https://example.org/{title}.torrent"),
+            size: 1536 * 1024 * 1024,
+            seeds,
+            leechs: 0,
+            provider: String::from("linuxtracker"),
+        }
+    }
+
+    fn search(user_id: &str, terms: &str, searched_at: i64) -> Search {
+        Search {
+            message_id: String::from("10"),
+            channel_id: String::from("1"),
+            user_id: user_id.to_string(),
+            terms: terms.to_string(),
+            results: vec![result("debian", 120), result("ubuntu", 80)],
+            searched_at,
+        }
+    }
+
+    #[test]
+    fn search_choices() {
+        let mut searches = Searches::default();
+        searches.record(search("alice", "linux", 1000));
+        searches.record(search("bob", "bsd", 1000));
+        searches.record(search("alice", "debian", 2000));
+
+        let last = searches.last("1", "alice", 2000).unwrap();
+        assert!(last.terms == "debian");
+        assert!(last.pick(1).unwrap().title == "debian");
+        assert!(last.pick(0).is_none() && last.pick(3).is_none());
+        assert!(last.pick(2).unwrap().summary() == "ubuntu, 1.5 GB, 80 seeders (linuxtracker)");
+        assert!(searches.last("2", "alice", 2000).is_none());
+        // too old to be picked from
+        assert!(searches.last("1", "bob", 1000 + 86400).is_none());
+    }
+}
@@ -0,0 +1,200 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use super::task::{RemoteTask, Task};
+use super::traits::DownloadingController;
+use super::units::{human_duration, parse_duration};
+
+// When to stop seeding a torrent once downloaded: as soon as the
+// ratio or the seeding time is reached, right away when neither is set
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SeedPolicy {
+    pub ratio: Option<f64>,
+    // seconds
+    pub time: Option<u64>,
+    // remove the task from Download Station instead of pausing it
+    pub remove: bool,
+}
+
+impl SeedPolicy {
+    // "2.0", "12h", "1.5 48h remove", "no"...
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("Seed: expects a ratio, a duration or no, such as 2.0 48h, got {value:?}");
+        let mut policy = Self::default();
+        for word in value
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|w| !w.is_empty())
+        {
+            match word.to_lowercase().as_str() {
+                "no" | "none" | "off" => {}
+                "remove" | "delete" => policy.remove = true,
+                "pause" => policy.remove = false,
+                word => match (word.parse::<f64>(), parse_duration(word)) {
+                    (Ok(ratio), _) if ratio.is_finite() && ratio >= 0.0 => {
+                        policy.ratio = Some(ratio)
+                    }
+                    (_, Some(time)) => policy.time = Some(time),
+                    _ => return Err(invalid()),
+                },
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn is_met(&self, ratio: f64, seeded_for: u64) -> bool {
+        match (self.ratio, self.time) {
+            (None, None) => true,
+            (target, time) => {
+                target.is_some_and(|target| ratio >= target)
+                    || time.is_some_and(|time| seeded_for >= time)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for SeedPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+// Pause or remove a seeding task once its policy is met, and tell
+// its requester. Tasks no longer seeding are left alone
+pub fn enforce<T: DownloadingController + ?Sized>(
+    controller: &T,
+    task: &mut Task,
+    remote: &[RemoteTask],
+    remove: bool,
+) {
+    let policy = match &task.request.seed {
+        Some(policy) => policy.clone(),
+        None => return,
+    };
+    let remote = match remote
+        .iter()
+        .find(|r| task.source.matches(r.uri.as_deref(), Some(&r.title)))
+    {
+        Some(remote) if remote.ds_status == "seeding" => remote,
+        // stopped or removed by someone else
+        _ => {
+            task.seeded = true;
+            return;
+        }
+    };
+    let ratio = remote.ratio();
+    if !policy.is_met(ratio, remote.seeded_for) {
+        return;
+    }
+    let (stopped, action) = match policy.remove || remove {
+        true => (controller.delete_task(&remote.id), "removed"),
+        false => (controller.pause_task(&remote.id), "paused"),
+    };
+    if !stopped {
+        // tried again on the next round
+        error!("Could not stop seeding task {}", remote.id);
+        return;
+    }
+    info!("Task {} seeded, {action}", task.message_id);
+    task.seeded = true;
+    let message = std::iter::once(&task.user_id)
+        .chain(task.request.notify.iter())
+        .fold(
+            format!(
+                "SEEDED: {}, ratio {ratio:.2} after {}, {action}",
+                remote.title,
+                human_duration(remote.seeded_for)
+            ),
+            |message, user| message + &format!(" <@{user}>"),
+        );
+    let notifier = task.notifier;
+    notifier.update_task_status(task, Some(&message));
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::{
+        request::Request,
+        seeding::{enforce, SeedPolicy},
+        task::tests::{remote, DsControlerMock},
+        task::{RemoteTask, Source, Task, TaskStatus},
+    };
+
+    // Downloaded, seeding for seeded_for seconds
+    fn seeding(uploaded: u64, seeded_for: u64) -> RemoteTask {
+        RemoteTask {
+            status: TaskStatus::DONE,
+            ds_status: "seeding".to_string(),
+            size: 1000,
+            downloaded: 1000,
+            uploaded,
+            seeded_for,
+            uri: Some("https://example.org/debian.iso".to_string()),
+            ..remote("dbid_1", "debian.iso")
+        }
+    }
+
+    #[test]
+    fn seed_policies() {
+        let policy = SeedPolicy::parse("1.5, 2d remove").unwrap();
+        assert!(policy.ratio == Some(1.5));
+        assert!(policy.time == Some(2 * 86400));
+        assert!(policy.remove);
+        assert!(!policy.is_met(1.0, 3600));
+        assert!(policy.is_met(1.5, 3600));
+        assert!(policy.is_met(0.1, 2 * 86400));
+        // stop seeding right away
+        assert!(SeedPolicy::parse("no").unwrap().is_met(0.0, 0));
+        assert!(SeedPolicy::parse("0").unwrap().is_met(0.0, 0));
+        assert!(SeedPolicy::parse("a lot").is_err());
+        assert!(SeedPolicy::parse("-1").is_err());
+    }
+
+    #[test]
+    fn seeding_enforcement() {
+        let mock = DsControlerMock {
+            tasks: vec![seeding(0, 0)],
+            ..Default::default()
+        };
+        let mut task = Task::new(
+            Source::URL("https://example.org/debian.iso".to_string()),
+            "1".to_string(),
+            "1".to_string(),
+            &mock,
+            None,
+            "42".to_string(),
+        );
+        task.request = Request::parse("Seed: 2 12h").unwrap();
+        task.set_status(TaskStatus::DONE);
+        assert!(task.is_seeding());
+
+        enforce(&mock, &mut task, &[seeding(1500, 3600)], false);
+        assert!(task.is_seeding());
+        enforce(&mock, &mut task, &[seeding(2500, 3600)], false);
+        assert!(!task.is_seeding());
+        assert!(mock.done.borrow()[1] == "pause dbid_1");
+        assert!(mock.done.borrow()[2] == "SEEDED: debian.iso, ratio 2.50 after 1h, paused <@42>");
+
+        // stopped from DSM: nothing to do
+        let mut task = Task::new(
+            Source::URL("https://example.org/debian.iso".to_string()),
+            "2".to_string(),
+            "1".to_string(),
+            &mock,
+            None,
+            "42".to_string(),
+        );
+        task.request = Request::parse("Seed: no").unwrap();
+        task.set_status(TaskStatus::DONE);
+        let mut finished = seeding(0, 0);
+        finished.ds_status = "finished".to_string();
+        enforce(&mock, &mut task, &[finished], true);
+        assert!(!task.is_seeding());
+        assert!(mock.done.borrow().len() == 4);
+    }
+}
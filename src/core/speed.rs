@@ -0,0 +1,234 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+
+use super::command::ChatCommand;
+use super::traits::SpeedController;
+use super::units::{human_size, parse_size};
+
+// Global bandwidth limits of Download Station, in KB/s, 0 meaning
+// unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeedLimits {
+    pub bt_download: u64,
+    pub bt_upload: u64,
+    pub http_download: u64,
+    pub ftp_download: u64,
+}
+
+impl SpeedLimits {
+    // The same download limit for every protocol
+    pub fn with_download(self, download: u64) -> Self {
+        Self {
+            bt_download: download,
+            http_download: download,
+            ftp_download: download,
+            ..self
+        }
+    }
+
+    pub fn with_upload(self, upload: u64) -> Self {
+        Self {
+            bt_upload: upload,
+            ..self
+        }
+    }
+}
+
+// "2MB" -> 2048 KB/s, "off" -> 0
+pub fn parse_limit(value: &str) -> Option<u64> {
+    match value.to_lowercase().as_str() {
+        "off" | "none" | "unlimited" => Some(0),
+        value => parse_size(value.trim_end_matches("/s")).map(|bytes| bytes / 1024),
+    }
+}
+
+fn human_limit(limit: u64) -> String {
+    match limit {
+        0 => String::from("unlimited"),
+        limit => format!("{}/s", human_size(limit * 1024)),
+    }
+}
+
+impl fmt::Display for SpeedLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BT ↓ {} ↑ {}, HTTP ↓ {}, FTP ↓ {}",
+            human_limit(self.bt_download),
+            human_limit(self.bt_upload),
+            human_limit(self.http_download),
+            human_limit(self.ftp_download)
+        )
+    }
+}
+
+// Limits lifted by !turbo, restored once it is over
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Boost {
+    pub nas: String,
+    pub until: i64,
+    pub limits: SpeedLimits,
+    // whether the alternative speed schedule was enabled
+    pub schedule: bool,
+    // the !turbo message, replied to once restored
+    pub channel_id: String,
+    pub message_id: String,
+    pub user_id: String,
+}
+
+impl Boost {
+    pub fn command(&self) -> ChatCommand {
+        ChatCommand {
+            message_id: self.message_id.clone(),
+            channel_id: self.channel_id.clone(),
+            user_id: self.user_id.clone(),
+            nas: Some(self.nas.clone()),
+            ..Default::default()
+        }
+    }
+
+    // Put the limits and the schedule back as they were
+    pub fn restore<T: SpeedController + ?Sized>(&self, controller: &T) -> Result<(), String> {
+        controller.set_speed_limits(&self.limits)?;
+        controller.set_schedule(self.schedule)
+    }
+}
+
+// The boosts in progress, kept between runs
+#[derive(Default)]
+pub struct Turbo {
+    path: Option<PathBuf>,
+    boosts: Vec<Boost>,
+}
+
+impl Turbo {
+    pub fn load(path: PathBuf) -> Self {
+        let boosts = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupted turbo file {}: {e}", path.display());
+                vec![]
+            }),
+            Err(_) => {
+                debug!("No turbo file at {}", path.display());
+                vec![]
+            }
+        };
+        Self {
+            path: Some(path),
+            boosts,
+        }
+    }
+
+    pub fn get(&self, nas: &str) -> Option<&Boost> {
+        self.boosts.iter().find(|b| b.nas == nas)
+    }
+
+    pub fn get_mut(&mut self, nas: &str) -> Option<&mut Boost> {
+        self.boosts.iter_mut().find(|b| b.nas == nas)
+    }
+
+    // A boost of a NAS already boosted only pushes back its end: the
+    // limits to restore are the ones from before the first
+    pub fn start(&mut self, boost: Boost) {
+        match self.get_mut(&boost.nas) {
+            Some(current) => current.until = boost.until,
+            None => self.boosts.push(boost),
+        }
+    }
+
+    pub fn stop(&mut self, nas: &str) -> Option<Boost> {
+        let index = self.boosts.iter().position(|b| b.nas == nas)?;
+        Some(self.boosts.remove(index))
+    }
+
+    // Boosts over at now
+    pub fn due(&self, now: i64) -> Vec<Boost> {
+        self.boosts
+            .iter()
+            .filter(|b| b.until <= now)
+            .cloned()
+            .collect()
+    }
+
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if self.boosts.is_empty() && !path.exists() {
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let written = serde_json::to_string(&self.boosts)
+            .map_err(|e| e.to_string())
+            .and_then(|content| fs::write(path, content).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Could not write turbo file {}: {e}", path.display());
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::{env, fs};
+
+    use crate::core::speed::{parse_limit, Boost, SpeedLimits, Turbo};
+
+    fn boost(nas: &str, until: i64, download: u64) -> Boost {
+        Boost {
+            nas: nas.to_string(),
+            until,
+            limits: SpeedLimits::default().with_download(download),
+            schedule: true,
+            channel_id: "1".to_string(),
+            message_id: "2".to_string(),
+            user_id: "3".to_string(),
+        }
+    }
+
+    #[test]
+    fn speed_limits() {
+        assert!(parse_limit("2MB") == Some(2048));
+        assert!(parse_limit("500KB/s") == Some(500));
+        assert!(parse_limit("off") == Some(0));
+        assert!(parse_limit("fast").is_none());
+        let limits = SpeedLimits::default().with_download(2048).with_upload(100);
+        assert!(limits.http_download == 2048 && limits.bt_upload == 100);
+        assert!(
+            limits.to_string() == "BT ↓ 2.0 MB/s ↑ 100.0 KB/s, HTTP ↓ 2.0 MB/s, FTP ↓ 2.0 MB/s"
+        );
+        assert!(SpeedLimits::default()
+            .to_string()
+            .starts_with("BT ↓ unlimited ↑ unlimited"));
+    }
+
+    #[test]
+    fn turbo_state() {
+        let path = env::temp_dir().join(format!("ds-companion-turbo-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut turbo = Turbo::load(path.clone());
+        turbo.start(boost("home", 100, 1024));
+        // boosting again keeps the limits from before the first boost
+        turbo.start(boost("home", 200, 0));
+        turbo.start(boost("office", 50, 512));
+        turbo.save();
+
+        let mut turbo = Turbo::load(path.clone());
+        let home = turbo.get("home").unwrap();
+        assert!(home.until == 200 && home.limits.bt_download == 1024);
+        let due: Vec<String> = turbo.due(150).into_iter().map(|b| b.nas).collect();
+        assert!(due == ["office"]);
+        assert!(turbo.stop("office").is_some());
+        assert!(turbo.stop("office").is_none());
+        assert!(turbo.due(150).is_empty());
+        fs::remove_file(path).unwrap();
+    }
+}
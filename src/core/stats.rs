@@ -0,0 +1,177 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use super::task::{RemoteTask, TaskStatus};
+use super::traits::{DownloadingController, StorageController};
+use super::units::human_size;
+
+// Longer task lists are summed up
+const MAX_LISTED_TASKS: usize = 15;
+
+// What the downloading backend tells about itself
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Statistics {
+    pub version: Option<String>,
+    // bytes per second
+    pub download_speed: u64,
+    pub upload_speed: u64,
+    pub default_destination: Option<String>,
+}
+
+// Free and total bytes of a shared folder's volume
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Space {
+    pub free: u64,
+    pub total: u64,
+}
+
+// State of the NAS and of its active tasks
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    pub statistics: Statistics,
+    // shared folder of the default destination
+    pub share: Option<String>,
+    pub space: Option<Space>,
+    pub active: Vec<RemoteTask>,
+    pub downloaded: u64,
+    pub size: u64,
+}
+
+impl Overview {
+    pub fn fetch<T: DownloadingController + StorageController + ?Sized>(
+        controller: &T,
+    ) -> Result<Self, String> {
+        let statistics = controller
+            .statistics()
+            .ok_or("Could not get Download Station statistics")?;
+        let tasks = controller
+            .list_tasks()
+            .ok_or("Could not list Download Station tasks")?;
+        let share = statistics
+            .default_destination
+            .as_deref()
+            .and_then(|d| d.trim_start_matches('/').split('/').next())
+            .filter(|share| !share.is_empty())
+            .map(String::from);
+        let space = share.as_deref().and_then(|s| controller.free_space(s));
+        Ok(Self::new(statistics, share, space, tasks))
+    }
+
+    pub fn new(
+        statistics: Statistics,
+        share: Option<String>,
+        space: Option<Space>,
+        tasks: Vec<RemoteTask>,
+    ) -> Self {
+        let active: Vec<RemoteTask> = tasks
+            .into_iter()
+            .filter(|t| t.status == TaskStatus::SUBMITTED || t.status == TaskStatus::DOWNLOADING)
+            .collect();
+        Self {
+            downloaded: active.iter().map(|t| t.downloaded).sum(),
+            size: active.iter().map(|t| t.size).sum(),
+            statistics,
+            share,
+            space,
+            active,
+        }
+    }
+}
+
+impl fmt::Display for Overview {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let statistics = &self.statistics;
+        writeln!(
+            f,
+            "Download Station {}, ↓ {}/s ↑ {}/s",
+            statistics.version.as_deref().unwrap_or("?"),
+            human_size(statistics.download_speed),
+            human_size(statistics.upload_speed)
+        )?;
+        if let (Some(share), Some(space)) = (&self.share, &self.space) {
+            writeln!(
+                f,
+                "Free space on {share}: {} of {}",
+                human_size(space.free),
+                human_size(space.total)
+            )?;
+        }
+        let progress = match self.size {
+            0 => 0.0,
+            size => self.downloaded as f64 * 100.0 / size as f64,
+        };
+        write!(
+            f,
+            "{} active task(s), {} of {} ({progress:.1}%)",
+            self.active.len(),
+            human_size(self.downloaded),
+            human_size(self.size)
+        )?;
+        for task in self.active.iter().take(MAX_LISTED_TASKS) {
+            write!(
+                f,
+                "\n• {}: {}, {:.1}% of {}",
+                task.title,
+                task.ds_status,
+                task.progress(),
+                human_size(task.size)
+            )?;
+        }
+        if self.active.len() > MAX_LISTED_TASKS {
+            write!(f, "\n… and {} more", self.active.len() - MAX_LISTED_TASKS)?;
+        }
+        Ok(())
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::{
+        stats::{Overview, Space, Statistics},
+        task::tests::remote,
+        task::{RemoteTask, TaskStatus},
+    };
+
+    fn task(title: &str, status: TaskStatus, downloaded: u64, size: u64) -> RemoteTask {
+        RemoteTask {
+            status,
+            ds_status: format!("{status:?}").to_lowercase(),
+            size,
+            downloaded,
+            ..remote(title, title)
+        }
+    }
+
+    #[test]
+    fn overview_report() {
+        let statistics = Statistics {
+            version: Some(String::from("3.9.2-4800")),
+            download_speed: 2 * 1024 * 1024,
+            upload_speed: 512 * 1024,
+            default_destination: Some(String::from("video/Downloads")),
+        };
+        let overview = Overview::new(
+            statistics,
+            Some(String::from("video")),
+            Some(Space {
+                free: 1024 * 1024 * 1024,
+                total: 4 * 1024 * 1024 * 1024,
+            }),
+            vec![
+                task("debian.iso", TaskStatus::DOWNLOADING, 1024, 4096),
+                task("ubuntu.iso", TaskStatus::SUBMITTED, 0, 4096),
+                task("arch.iso", TaskStatus::DONE, 4096, 4096),
+            ],
+        );
+        let report = overview.to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0] == "Download Station 3.9.2-4800, ↓ 2.0 MB/s ↑ 512.0 KB/s");
+        assert!(lines[1] == "Free space on video: 1.0 GB of 4.0 GB");
+        assert!(lines[2] == "2 active task(s), 1.0 KB of 8.0 KB (12.5%)");
+        assert!(lines[3] == "• debian.iso: downloading, 25.0% of 4.0 KB");
+        assert!(lines.len() == 5);
+    }
+}
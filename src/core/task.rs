@@ -1,10 +1,14 @@
 use bytes::Bytes;
 use core::fmt;
+use serde::{Deserialize, Serialize};
 use traits::MessagingController;
 
+use super::magnet::Magnet;
+use super::request::Request;
+use super::torrent::Torrent;
 use super::traits;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum TaskStatus {
     RECEIVED,
     SUBMITTED,
@@ -23,21 +27,160 @@ impl fmt::Display for TaskStatus {
 pub enum Source {
     FILE(Bytes),
     MAGNET(String),
+    URL(String),
+}
+
+impl Source {
+    // Build a source from a link given by a user. Magnet links are
+    // checked and normalised
+    pub fn from_uri(uri: &str) -> Result<Self, String> {
+        let uri = uri.trim();
+        if uri.starts_with("magnet:") {
+            Ok(Source::MAGNET(Magnet::parse(uri)?.to_string()))
+        } else if ["http://", "https://", "ftp://"]
+            .iter()
+            .any(|scheme| uri.starts_with(scheme))
+        {
+            Ok(Source::URL(uri.to_string()))
+        } else {
+            Err(format!("{uri} is neither a magnet link nor an URL"))
+        }
+    }
+
+    // Name of the download, when the source tells it
+    pub fn name(&self) -> Option<String> {
+        match self {
+            Source::MAGNET(_) => self.magnet()?.name,
+            Source::URL(link) => link
+                .split(['?', '#'])
+                .next()?
+                .rsplit('/')
+                .next()
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    urlencoding::decode(name)
+                        .map(|n| n.into_owned())
+                        .unwrap_or(name.to_string())
+                }),
+            Source::FILE(_) => self.torrent().map(|t| t.name),
+        }
+    }
+
+    pub fn magnet(&self) -> Option<Magnet> {
+        match self {
+            Source::MAGNET(link) => Magnet::parse(link).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn torrent(&self) -> Option<Torrent> {
+        match self {
+            Source::FILE(file) => Torrent::parse(file).ok(),
+            _ => None,
+        }
+    }
+
+    // Host names of the trackers, for display
+    pub fn tracker_hosts(&self) -> Vec<String> {
+        let trackers = match self {
+            Source::MAGNET(_) => self.magnet().map(|m| m.trackers),
+            Source::FILE(_) => self.torrent().map(|t| t.trackers),
+            Source::URL(_) => None,
+        };
+        trackers
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|t| reqwest::Url::parse(t).ok()?.host_str().map(String::from))
+            .collect()
+    }
+
+    // Whether a task of the downloading backend, known by its uri and
+    // title, is this very download
+    pub fn matches(&self, uri: Option<&str>, title: Option<&str>) -> bool {
+        match self {
+            Source::MAGNET(link) | Source::URL(link) => uri.is_some_and(|uri| {
+                // the backend may report a magnet link in another form
+                uri == link || Magnet::parse(uri).is_ok_and(|m| Some(m.key()) == self.key())
+            }),
+            // uploaded torrents are only known by their name
+            Source::FILE(_) => title.is_some() && self.name().as_deref() == title,
+        }
+    }
+
+    // Identifies the download whatever its form, to tell duplicates
+    pub fn key(&self) -> Option<String> {
+        match self {
+            Source::MAGNET(_) => self.magnet().map(|m| m.key()),
+            Source::URL(link) => Some(link.clone()),
+            Source::FILE(_) => self.torrent().map(|t| t.key()),
+        }
+    }
+}
+
+// A task as known by the downloading backend, whatever
+// its origin (chat, API, DSM UI...)
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteTask {
+    pub id: String,
+    pub title: String,
+    pub status: TaskStatus,
+    pub ds_status: String,
+    pub size: u64,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    // seconds spent seeding
+    pub seeded_for: u64,
+    pub created_at: Option<i64>,
+    pub completed_at: Option<i64>,
+    pub uri: Option<String>,
+    pub destination: Option<String>,
+}
+
+impl RemoteTask {
+    pub fn progress(&self) -> f64 {
+        if self.size == 0 {
+            0.0
+        } else {
+            self.downloaded as f64 * 100.0 / self.size as f64
+        }
+    }
+
+    // Uploaded over downloaded, as DSM shows it
+    pub fn ratio(&self) -> f64 {
+        match self.downloaded.max(self.size) {
+            0 => 0.0,
+            downloaded => self.uploaded as f64 / downloaded as f64,
+        }
+    }
 }
 
 pub struct Task<'a> {
     status: TaskStatus,
     pub message_id: String,
+    pub channel_id: String,
     pub source: Source,
     pub notifier: &'a dyn MessagingController,
     pub destination_folder: Option<String>,
     pub user_id: String,
+    // Synology backend the task goes to, the default one if None
+    pub nas: Option<String>,
+    // Size reported by the downloading backend, once known
+    pub size: Option<u64>,
+    // Options given along with the download
+    pub request: Request,
+    // Names of the files downloaded, when only some were selected
+    pub selected_files: Option<Vec<String>>,
+    // Seeding stopped, by its policy or by someone else
+    pub seeded: bool,
+    // Where the download was moved or renamed to once organised
+    pub location: Option<String>,
 }
 
 impl<'a> Task<'a> {
     pub fn new(
         source: Source,
         message_id: String,
+        channel_id: String,
         notifier: &'a dyn MessagingController,
         destination_folder: Option<String>,
         user_id: String,
@@ -45,10 +188,17 @@ impl<'a> Task<'a> {
         Self {
             source,
             message_id,
+            channel_id,
             status: TaskStatus::RECEIVED,
             notifier,
             destination_folder,
             user_id,
+            nas: None,
+            size: None,
+            request: Request::default(),
+            selected_files: None,
+            seeded: false,
+            location: None,
         }
     }
     // Update private field status and call the associated
@@ -58,7 +208,151 @@ impl<'a> Task<'a> {
         self.notifier.update_task_status(self, None);
     }
 
+    // Same as set_status, with a message explaining the change
+    pub fn set_status_and_notify(&mut self, status: TaskStatus, message: &str) {
+        self.status = status;
+        self.notifier.update_task_status(self, Some(message));
+    }
+
     pub fn get_status(&self) -> TaskStatus {
         self.status
     }
+
+    pub fn is_active(&self) -> bool {
+        self.status != TaskStatus::DONE && self.status != TaskStatus::FAILED
+    }
+
+    // Downloaded, still seeding until its policy is met
+    pub fn is_seeding(&self) -> bool {
+        self.status == TaskStatus::DONE && self.request.seed.is_some() && !self.seeded
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    use crate::core::{
+        command::ChatCommand,
+        destination::Folder,
+        organize::Entry,
+        search::Search,
+        stats::{Space, Statistics},
+        task::{RemoteTask, Task, TaskStatus},
+        traits::{DownloadingController, MessagingController, StorageController},
+    };
+
+    // A task of Download Station, halfway through
+    pub fn remote(id: &str, title: &str) -> RemoteTask {
+        RemoteTask {
+            id: id.to_string(),
+            title: title.to_string(),
+            status: TaskStatus::DOWNLOADING,
+            ds_status: String::from("downloading"),
+            size: 100,
+            downloaded: 50,
+            uploaded: 0,
+            seeded_for: 0,
+            created_at: None,
+            completed_at: None,
+            uri: None,
+            destination: None,
+        }
+    }
+
+    // Download Station and its files. Accepts every task, acts on the
+    // listed ones only, and keeps track of what it did and was told
+    #[derive(Default)]
+    pub struct DsControlerMock {
+        pub tasks: Vec<RemoteTask>,
+        // files of each folder
+        pub files: BTreeMap<String, Vec<Entry>>,
+        pub done: RefCell<Vec<String>>,
+    }
+
+    impl DsControlerMock {
+        fn act(&self, verb: &str, id: &str) -> bool {
+            let known = self.tasks.iter().any(|t| t.id == id);
+            if known {
+                self.done.borrow_mut().push(format!("{verb} {id}"));
+            }
+            known
+        }
+    }
+
+    impl MessagingController for DsControlerMock {
+        fn fetch_tasks(&self) -> Option<Vec<Task>> {
+            None
+        }
+        fn update_task_status(&self, _: &mut Task, message: Option<&str>) {
+            self.done
+                .borrow_mut()
+                .push(message.unwrap_or_default().to_string());
+        }
+        fn fetch_commands(&self) -> Vec<ChatCommand> {
+            vec![]
+        }
+        fn reply(&self, _: &ChatCommand, _: &str) {}
+        fn offer(&self, _: &ChatCommand, _: &Search) {}
+    }
+
+    impl DownloadingController for DsControlerMock {
+        fn submit_task(&self, task: &mut Task) {
+            let destination = task.destination_folder.as_deref().unwrap_or_default();
+            self.done
+                .borrow_mut()
+                .push(format!("submit to {destination}"));
+            task.set_status(TaskStatus::SUBMITTED);
+        }
+        fn get_jobs_advancement(&self, _: &mut Vec<Task>) {}
+        fn list_tasks(&self) -> Option<Vec<RemoteTask>> {
+            Some(self.tasks.clone())
+        }
+        fn get_task(&self, id: &str) -> Option<RemoteTask> {
+            self.tasks.iter().find(|t| t.id == id).cloned()
+        }
+        fn delete_task(&self, id: &str) -> bool {
+            self.act("delete", id)
+        }
+        fn pause_task(&self, id: &str) -> bool {
+            self.act("pause", id)
+        }
+        fn resume_task(&self, id: &str) -> bool {
+            self.act("resume", id)
+        }
+        fn statistics(&self) -> Option<Statistics> {
+            None
+        }
+    }
+
+    impl StorageController for DsControlerMock {
+        fn list_folders(&self, _: &str) -> Option<Vec<Folder>> {
+            None
+        }
+        fn create_folder(&self, _: &str, _: &str) -> bool {
+            false
+        }
+        fn list_files(&self, path: &str) -> Option<Vec<Entry>> {
+            self.files.get(path).cloned()
+        }
+        fn move_to(&self, _: &str, _: &str) -> Result<(), String> {
+            Ok(())
+        }
+        fn rename(&self, _: &str, _: &str) -> Result<(), String> {
+            Ok(())
+        }
+        fn extract(&self, _: &str, _: &str) -> Result<(), String> {
+            Ok(())
+        }
+        fn delete(&self, path: &str) -> Result<(), String> {
+            self.done.borrow_mut().push(format!("rm {path}"));
+            Ok(())
+        }
+        fn free_space(&self, _: &str) -> Option<Space> {
+            None
+        }
+    }
 }
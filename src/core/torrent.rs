@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use sha1_smol::Sha1;
+
+// .torrent files bigger than this are not even decoded
+const MAX_METAINFO_SIZE: usize = 10 * 1024 * 1024;
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, PartialEq)]
+enum Bencode<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Bencode<'a>>),
+    Dict(BTreeMap<&'a [u8], Bencode<'a>>),
+}
+
+impl<'a> Bencode<'a> {
+    fn get(&self, key: &str) -> Option<&Bencode<'a>> {
+        match self {
+            Bencode::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn str(&self) -> Option<String> {
+        match self {
+            Bencode::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> &[Bencode<'a>] {
+        match self {
+            Bencode::List(list) => list,
+            _ => &[],
+        }
+    }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    // where the info dictionary lies, its hash identifies the torrent
+    info: Option<(usize, usize)>,
+}
+
+impl<'a> Decoder<'a> {
+    fn error(&self, what: &str) -> String {
+        format!("invalid torrent file: {what} at byte {}", self.position)
+    }
+
+    fn next(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or(self.error("unexpected end"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn until(&mut self, end: u8) -> Result<&'a str, String> {
+        let start = self.position;
+        while self.next()? != end {}
+        std::str::from_utf8(&self.data[start..self.position - 1])
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn decode(&mut self, depth: usize) -> Result<Bencode<'a>, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        match self.next()? {
+            b'i' => {
+                let number = self.until(b'e')?;
+                number
+                    .parse()
+                    .map(Bencode::Int)
+                    .map_err(|_| self.error("invalid integer"))
+            }
+            b'l' => {
+                let mut list = vec![];
+                while self.data.get(self.position) != Some(&b'e') {
+                    list.push(self.decode(depth + 1)?);
+                }
+                self.position += 1;
+                Ok(Bencode::List(list))
+            }
+            b'd' => {
+                let mut dict = BTreeMap::new();
+                while self.data.get(self.position) != Some(&b'e') {
+                    let key = match self.decode(depth + 1)? {
+                        Bencode::Bytes(key) => key,
+                        _ => return Err(self.error("dictionary key is not a string")),
+                    };
+                    let start = self.position;
+                    let value = self.decode(depth + 1)?;
+                    if depth == 0 && key == b"info" {
+                        self.info = Some((start, self.position));
+                    }
+                    dict.insert(key, value);
+                }
+                self.position += 1;
+                Ok(Bencode::Dict(dict))
+            }
+            b'0'..=b'9' => {
+                self.position -= 1;
+                let length: usize = self
+                    .until(b':')?
+                    .parse()
+                    .map_err(|_| self.error("invalid length"))?;
+                let end = self
+                    .position
+                    .checked_add(length)
+                    .filter(|end| *end <= self.data.len());
+                let end = end.ok_or(self.error("string out of bounds"))?;
+                let bytes = &self.data[self.position..end];
+                self.position = end;
+                Ok(Bencode::Bytes(bytes))
+            }
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentFile {
+    pub path: String,
+    pub size: u64,
+}
+
+// What a .torrent file tells about the download
+#[derive(Debug, Clone, PartialEq)]
+pub struct Torrent {
+    pub name: String,
+    // v1 info-hash, lowercase hex
+    pub info_hash: String,
+    pub size: u64,
+    pub files: Vec<TorrentFile>,
+    pub piece_length: u64,
+    pub private: bool,
+    pub trackers: Vec<String>,
+}
+
+impl Torrent {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() > MAX_METAINFO_SIZE {
+            return Err(String::from("torrent file too big"));
+        }
+        let mut decoder = Decoder {
+            data,
+            position: 0,
+            info: None,
+        };
+        let root = decoder.decode(0)?;
+        let (start, end) = decoder
+            .info
+            .ok_or("invalid torrent file: no info dictionary")?;
+        let info = root.get("info").unwrap();
+
+        let name = info
+            .get("name.utf-8")
+            .or(info.get("name"))
+            .and_then(Bencode::str)
+            .ok_or("invalid torrent file: no name")?;
+        let piece_length = info
+            .get("piece length")
+            .and_then(Bencode::int)
+            .filter(|l| *l > 0)
+            .ok_or("invalid torrent file: no piece length")? as u64;
+        let files = match (info.get("length").and_then(Bencode::int), info.get("files")) {
+            (Some(length), _) if length >= 0 => vec![TorrentFile {
+                path: name.clone(),
+                size: length as u64,
+            }],
+            (None, Some(Bencode::List(files))) => files
+                .iter()
+                .map(|file| {
+                    let size = file
+                        .get("length")
+                        .and_then(Bencode::int)
+                        .filter(|l| *l >= 0);
+                    let path: Option<Vec<String>> = file
+                        .get("path.utf-8")
+                        .or(file.get("path"))
+                        .map(|p| p.list().iter().filter_map(Bencode::str).collect());
+                    match (size, path) {
+                        (Some(size), Some(path)) if !path.is_empty() => Ok(TorrentFile {
+                            path: path.join("/"),
+                            size: size as u64,
+                        }),
+                        _ => Err(String::from("invalid torrent file: invalid file entry")),
+                    }
+                })
+                .collect::<Result<Vec<TorrentFile>, String>>()?,
+            _ => return Err(String::from("invalid torrent file: no length nor files")),
+        };
+
+        // announce-list tiers first, then the single announce
+        let mut trackers: Vec<String> = vec![];
+        let tiers = root
+            .get("announce-list")
+            .map(Bencode::list)
+            .unwrap_or_default();
+        for tracker in tiers
+            .iter()
+            .flat_map(Bencode::list)
+            .chain(root.get("announce"))
+        {
+            if let Some(tracker) = tracker.str().filter(|t| !trackers.contains(t)) {
+                trackers.push(tracker);
+            }
+        }
+
+        let size = files
+            .iter()
+            .try_fold(0u64, |size, f| size.checked_add(f.size))
+            .ok_or("invalid torrent file: too big")?;
+
+        Ok(Self {
+            name,
+            info_hash: Sha1::from(&data[start..end]).digest().to_string(),
+            size,
+            files,
+            piece_length,
+            private: info.get("private").and_then(Bencode::int) == Some(1),
+            trackers,
+        })
+    }
+
+    // Same as Magnet::key, a torrent and its magnet link are the same
+    pub fn key(&self) -> String {
+        format!("urn:btih:{}", self.info_hash)
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use crate::core::{magnet::Magnet, torrent::Torrent};
+
+    // a multi-file torrent, as mktorrent would write it
+    pub fn torrent_file() -> Vec<u8> {
+        let info = b"d5:filesld6:lengthi1024e4:pathl3:iso9:disk1.isoeed6:lengthi512e4:pathl10:README.txteee4:name6:debian12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1ee";
+        [
+            b"d8:announce39:udp://tracker.example.org:6969/announce13:announce-listll39:udp://tracker.example.org:6969/announceel28:http://other.example.net/annee4:info".as_slice(),
+            info,
+            b"e",
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn torrent_parsing() {
+        let torrent = Torrent::parse(&torrent_file()).unwrap();
+        assert!(torrent.name == "debian");
+        assert!(torrent.size == 1536);
+        assert!(torrent.files.len() == 2);
+        assert!(torrent.files[0].path == "iso/disk1.iso");
+        assert!(torrent.piece_length == 16384);
+        assert!(torrent.private);
+        assert!(
+            torrent.trackers
+                == [
+                    "udp://tracker.example.org:6969/announce",
+                    "http://other.example.net/ann"
+                ]
+        );
+        // sha1 of the info dictionary
+        assert!(torrent.info_hash == "b8e9eb764ddcc61d8dddd5e57e9356586ba8f959");
+        let magnet = Magnet::parse(&format!(
+            "magnet:?xt=urn:btih:{}",
+            torrent.info_hash.to_uppercase()
+        ));
+        assert!(magnet.unwrap().key() == torrent.key());
+
+        let single =
+            Torrent::parse(b"d4:infod6:lengthi42e4:name8:file.iso12:piece lengthi1024eee").unwrap();
+        assert!(single.size == 42 && single.files[0].path == "file.iso" && !single.private);
+
+        assert!(Torrent::parse(b"").is_err());
+        assert!(Torrent::parse(b"not a torrent").is_err());
+        assert!(Torrent::parse(b"d4:infod4:name1:xee").is_err());
+        assert!(
+            Torrent::parse(b"d4:infod6:lengthi42e4:name99:file.iso12:piece lengthi1024eee")
+                .is_err()
+        );
+        assert!(Torrent::parse(&b"l".repeat(100)).is_err());
+        // sizes summing beyond u64
+        let file = "d6:lengthi9223372036854775807e4:pathl1:aee";
+        let huge = format!(
+            "d4:infod5:filesl{}e4:name1:x12:piece lengthi1024eee",
+            file.repeat(3)
+        );
+        assert!(Torrent::parse(huge.as_bytes()).unwrap_err() == "invalid torrent file: too big");
+    }
+}
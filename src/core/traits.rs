@@ -6,30 +6,87 @@ use reqwest::{
 };
 use serde_json::Value;
 
-use super::task::Task;
+use super::command::ChatCommand;
+use super::destination::Folder;
+use super::organize::Entry;
+use super::rss::{RssItem, RssSite};
+use super::search::{Search, SearchResult};
+use super::speed::SpeedLimits;
+use super::stats::{Space, Statistics};
+use super::task::{RemoteTask, Task};
 
 pub trait MessagingController {
-    fn new() -> Self
-    where
-        Self: Sized;
     fn fetch_tasks(&self) -> Option<Vec<Task>>;
     fn update_task_status(&self, task: &mut Task, message: Option<&str>);
+    // Commands posted by users allowed to
+    fn fetch_commands(&self) -> Vec<ChatCommand>;
+    fn reply(&self, command: &ChatCommand, content: &str);
+    // Reply with search results to pick from
+    fn offer(&self, command: &ChatCommand, search: &Search);
 }
 
 pub trait DownloadingController {
-    fn new() -> Self;
     fn submit_task(&self, task: &mut Task);
     fn get_jobs_advancement(&self, tasks: &mut Vec<Task>);
+    fn list_tasks(&self) -> Option<Vec<RemoteTask>>;
+    fn get_task(&self, id: &str) -> Option<RemoteTask>;
+    fn delete_task(&self, id: &str) -> bool;
+    fn pause_task(&self, id: &str) -> bool;
+    fn resume_task(&self, id: &str) -> bool;
+    fn statistics(&self) -> Option<Statistics>;
+}
+
+pub trait StorageController {
+    // Sub folders of a folder, the shared folders for "". None
+    // if it could not be listed
+    fn list_folders(&self, path: &str) -> Option<Vec<Folder>>;
+    // Create name (and its missing parents) under parent
+    fn create_folder(&self, parent: &str, name: &str) -> bool;
+    // Files and folders of a folder, None if it could not be listed
+    fn list_files(&self, path: &str) -> Option<Vec<Entry>>;
+    // These wait for the operation to be over
+    fn move_to(&self, path: &str, folder: &str) -> Result<(), String>;
+    fn rename(&self, path: &str, name: &str) -> Result<(), String>;
+    fn extract(&self, archive: &str, folder: &str) -> Result<(), String>;
+    fn delete(&self, path: &str) -> Result<(), String>;
+    // Space left on the volume of a shared folder
+    fn free_space(&self, share: &str) -> Option<Space>;
+}
+
+pub trait SpeedController {
+    // Global bandwidth limits, None if they could not be read
+    fn speed_limits(&self) -> Option<SpeedLimits>;
+    fn set_speed_limits(&self, limits: &SpeedLimits) -> Result<(), String>;
+    // Whether the alternative speed schedule is enabled
+    fn schedule_enabled(&self) -> Option<bool>;
+    fn set_schedule(&self, enabled: bool) -> Result<(), String>;
+}
+
+pub trait RssController {
+    // RSS sites subscribed in Download Station, None if they could not be listed
+    fn list_rss_sites(&self) -> Option<Vec<RssSite>>;
+    fn add_rss_site(&self, url: &str) -> Result<(), String>;
+    // Every site when id is None
+    fn refresh_rss_site(&self, id: Option<&str>) -> Result<(), String>;
+    fn delete_rss_site(&self, id: &str) -> Result<(), String>;
+    // Latest items of a site
+    fn list_rss_items(&self, id: &str) -> Option<Vec<RssItem>>;
+}
+
+pub trait SearchController {
+    // Torrents matching the terms, most seeded first. The category is
+    // one of the search engine's, by name
+    fn search(&self, terms: &str, category: Option<&str>) -> Result<Vec<SearchResult>, String>;
 }
 
-#[allow(dead_code)]
 pub enum Payload {
     BODY(Body),
-    FORM(Form), // dead code, to be implemented
+    FORM(Form),
 }
 
 pub trait HTTPService {
-    fn new() -> Self;
+    type Conf;
+    fn new(conf: &Self::Conf) -> Self;
     fn send_request(&self, url: Url, method: Method, payload: Option<Payload>) -> Option<Value>;
     fn download_file(&self, url: Url) -> Option<Bytes>;
 }
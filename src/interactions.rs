@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::api::ApiResponse;
+use crate::conf::AccessConf;
+use crate::core::command::TaskAction;
+use crate::core::destination;
+use crate::core::history::{History, HistoryEntry};
+use crate::core::task::RemoteTask;
+use crate::core::traits::{DownloadingController, StorageController};
+
+// Interaction and response types of discord
+const PING: u64 = 1;
+const COMPONENT: u64 = 3;
+const PONG: u64 = 1;
+const CHANNEL_MESSAGE: u64 = 4;
+// only shown to the user who clicked
+const EPHEMERAL: u64 = 64;
+// Requests signed longer ago than that, in seconds, may be replays
+const MAX_REQUEST_AGE: i64 = 300;
+
+// Handles the clicks on the buttons of the status messages, which
+// discord posts to the interactions endpoint
+pub struct Interactions<'a, T> {
+    public_key: VerifyingKey,
+    controllers: BTreeMap<String, &'a T>,
+    default_nas: String,
+    history: PathBuf,
+    access: AccessConf,
+}
+
+impl<'a, T: DownloadingController + StorageController> Interactions<'a, T> {
+    pub fn new(
+        public_key: &str,
+        controllers: BTreeMap<String, &'a T>,
+        default_nas: String,
+        history: PathBuf,
+        access: AccessConf,
+    ) -> Result<Self, String> {
+        let bytes: [u8; 32] = hex::decode(public_key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or("invalid discord public key")?;
+        let public_key = VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string())?;
+        Ok(Self {
+            public_key,
+            controllers,
+            default_nas,
+            history,
+            access,
+        })
+    }
+
+    pub fn handle(
+        &self,
+        signature: Option<&str>,
+        timestamp: Option<&str>,
+        body: &[u8],
+    ) -> ApiResponse {
+        // discord checks that unsigned requests are refused
+        if !self.verify(signature, timestamp, body) {
+            return ApiResponse::error(401, "Invalid request signature");
+        }
+        let interaction: Value = match serde_json::from_slice(body) {
+            Ok(interaction) => interaction,
+            Err(e) => return ApiResponse::error(400, &format!("Invalid body: {e}")),
+        };
+        match interaction["type"].as_u64() {
+            Some(PING) => ApiResponse::new(200, json!({ "type": PONG })),
+            Some(COMPONENT) => {
+                let data = match self.click(&interaction) {
+                    Ok(content) => json!({"content": content, "allowed_mentions": {"parse": []}}),
+                    Err(e) => json!({"content": e, "flags": EPHEMERAL}),
+                };
+                ApiResponse::new(200, json!({ "type": CHANNEL_MESSAGE, "data": data }))
+            }
+            _ => ApiResponse::error(400, "Unsupported interaction"),
+        }
+    }
+
+    fn verify(&self, signature: Option<&str>, timestamp: Option<&str>, body: &[u8]) -> bool {
+        let signature = signature
+            .and_then(|s| hex::decode(s).ok())
+            .and_then(|s| Signature::from_slice(&s).ok());
+        match (signature, timestamp) {
+            (Some(signature), Some(timestamp)) => {
+                let fresh = timestamp
+                    .parse::<i64>()
+                    .is_ok_and(|t| (Utc::now().timestamp() - t).abs() <= MAX_REQUEST_AGE);
+                let message = [timestamp.as_bytes(), body].concat();
+                fresh && self.public_key.verify(&message, &signature).is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    // The message telling what was done, or why nothing was
+    fn click(&self, interaction: &Value) -> Result<String, String> {
+        let custom_id = interaction["data"]["custom_id"]
+            .as_str()
+            .unwrap_or_default();
+        let (action, message_id) = TaskAction::parse(custom_id).ok_or("Unknown button")?;
+        let user_id = interaction["member"]["user"]["id"]
+            .as_str()
+            .or(interaction["user"]["id"].as_str())
+            .ok_or("Unknown user")?;
+        let roles: Vec<&str> = interaction["member"]["roles"]
+            .as_array()
+            .map(|roles| roles.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        // denied even when admin, as for messages
+        if self.access.denied_users.iter().any(|u| u == user_id)
+            || roles
+                .iter()
+                .any(|r| self.access.denied_roles.iter().any(|d| d == r))
+        {
+            return Err(format!("Sorry <@{user_id}>, you may not do that"));
+        }
+
+        let history = History::load(self.history.clone());
+        let entry = history
+            .find(&message_id)
+            .ok_or("This download is not followed anymore")?;
+        let admin = self.access.admin_users.iter().any(|u| u == user_id)
+            || roles
+                .iter()
+                .any(|r| self.access.admin_roles.iter().any(|a| a == r));
+        if entry.user_id != user_id && !admin {
+            return Err(format!(
+                "Sorry <@{user_id}>, only <@{}> or admins may do that",
+                entry.user_id
+            ));
+        }
+        let nas = entry.nas.as_deref().unwrap_or(&self.default_nas);
+        let controller = self
+            .controllers
+            .get(nas)
+            .ok_or(format!("Unknown synology {nas}"))?;
+        let remote = controller
+            .list_tasks()
+            .ok_or("Could not list Download Station tasks")?
+            .into_iter()
+            .find(|r| entry.matches(r))
+            .ok_or("This download is no longer in Download Station")?;
+
+        apply(*controller, &action, entry, &remote)?;
+        info!(
+            "Task {} {} by {user_id} ({})",
+            remote.id,
+            action.past(),
+            entry.message_id
+        );
+        Ok(format!(
+            "{} {} by <@{user_id}>",
+            remote.title,
+            action.past()
+        ))
+    }
+}
+
+fn apply<T>(
+    controller: &T,
+    action: &TaskAction,
+    entry: &HistoryEntry,
+    remote: &RemoteTask,
+) -> Result<(), String>
+where
+    T: DownloadingController + StorageController + ?Sized,
+{
+    // found before anything is cancelled
+    let files = match action {
+        TaskAction::DELETE => Some(files(controller, entry, remote)?),
+        _ => None,
+    };
+    let done = match action {
+        TaskAction::PAUSE => controller.pause_task(&remote.id),
+        TaskAction::RESUME => controller.resume_task(&remote.id),
+        TaskAction::CANCEL | TaskAction::DELETE => controller.delete_task(&remote.id),
+    };
+    if !done {
+        return Err(format!(
+            "Download Station could not {} {}",
+            action.label().to_lowercase(),
+            remote.title
+        ));
+    }
+    if let Some(path) = files {
+        if let Err(e) = controller.delete(&path) {
+            warn!("Could not delete {path}: {e}");
+            return Err(format!(
+                "{} cancelled, could not delete {path}",
+                remote.title
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The file or folder downloaded, where organizing put it. Only an
+// existing path within a shared folder is ever deleted
+fn files<T: StorageController + ?Sized>(
+    storage: &T,
+    entry: &HistoryEntry,
+    remote: &RemoteTask,
+) -> Result<String, String> {
+    let unknown = || format!("Where {} is is unknown, no file deleted", remote.title);
+    let path = match &entry.location {
+        Some(location) => location.clone(),
+        None => {
+            let title = remote.title.as_str();
+            if title.is_empty() || title == "." || title == ".." || title.contains('/') {
+                return Err(unknown());
+            }
+            let destination = remote.destination.as_deref().ok_or_else(unknown)?;
+            format!("{destination}/{title}")
+        }
+    };
+    let path = destination::normalize(&path).map_err(|_| unknown())?;
+    let (parent, name) = path.rsplit_once('/').ok_or_else(unknown)?;
+    let exists = storage
+        .list_files(&format!("/{parent}"))
+        .is_some_and(|entries| entries.iter().any(|e| e.name == name));
+    match exists {
+        true => Ok(format!("/{path}")),
+        false => Err(format!("{path} not found, no file deleted")),
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeMap;
+    use std::{env, fs};
+
+    use chrono::Utc;
+    use ed25519_dalek::{Signer, SigningKey};
+    use serde_json::{json, Value};
+
+    use crate::{
+        conf::AccessConf,
+        core::organize::Entry,
+        core::task::tests::{remote, DsControlerMock},
+        core::task::RemoteTask,
+        interactions::Interactions,
+    };
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn controller() -> DsControlerMock {
+        let task = |id: &str, title: &str| RemoteTask {
+            uri: Some(format!("https://example.org/{id}.iso")),
+            destination: Some("downloads/".to_string()),
+            ..remote(id, title)
+        };
+        let file = |name: &str| {
+            vec![Entry {
+                name: name.to_string(),
+                is_dir: false,
+            }]
+        };
+        DsControlerMock {
+            tasks: vec![
+                task("dbid_1", "debian.iso"),
+                task("dbid_2", "debian-12.iso"),
+                task("dbid_3", ".."),
+            ],
+            files: BTreeMap::from([
+                (String::from("/downloads"), file("debian.iso")),
+                (String::from("/video"), file("Debian 12.iso")),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn click(custom_id: &str, user_id: &str, roles: &[&str]) -> Value {
+        json!({
+            "type": 3,
+            "data": {"custom_id": custom_id},
+            "member": {"user": {"id": user_id}, "roles": roles},
+        })
+    }
+
+    fn signed(body: &Value, timestamp: &str) -> (String, Vec<u8>) {
+        let body = body.to_string().into_bytes();
+        let message = [timestamp.as_bytes(), &body].concat();
+        let signature = SigningKey::from_bytes(&KEY).sign(&message);
+        (hex::encode(signature.to_bytes()), body)
+    }
+
+    #[test]
+    fn button_clicks() {
+        let path = env::temp_dir().join(format!(
+            "ds-companion-interactions-{}.json",
+            std::process::id()
+        ));
+        let entry = |message_id: &str, id: &str, location: Option<&str>| {
+            json!({
+                "message_id": message_id, "channel_id": "1", "user_id": "alice", "nas": null,
+                "uri": format!("https://example.org/{id}.iso"), "size": 0, "status": "DONE",
+                "submitted_at": 0, "location": location,
+            })
+        };
+        let mut uploaded = entry("14", "", None);
+        uploaded["uri"] = Value::Null;
+        uploaded["name"] = json!("debian-12.iso");
+        // another download of the same name
+        let mut homonym = entry("15", "other", None);
+        homonym["name"] = json!("debian.iso");
+        let history = json!([
+            entry("10", "dbid_1", None),
+            entry("12", "dbid_2", Some("/video/Debian 12.iso")),
+            entry("13", "dbid_3", None),
+            uploaded,
+            homonym,
+        ]);
+        fs::write(&path, history.to_string()).unwrap();
+        let controller = controller();
+        let public_key = hex::encode(SigningKey::from_bytes(&KEY).verifying_key().to_bytes());
+        let interactions = Interactions::new(
+            &public_key,
+            BTreeMap::from([(String::from("home"), &controller)]),
+            String::from("home"),
+            path.clone(),
+            AccessConf {
+                admin_roles: vec![String::from("ops")],
+                denied_users: vec![String::from("mallory")],
+                denied_roles: vec![String::from("kids")],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let now = Utc::now().timestamp().to_string();
+        let timestamp = Some(now.as_str());
+
+        let (signature, body) = signed(&json!({"type": 1}), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.status == 200 && resp.body["type"] == 1);
+        // unsigned or tampered requests are refused
+        assert!(interactions.handle(None, timestamp, &body).status == 401);
+        let resp = interactions.handle(Some(&signature), timestamp, b"{\"type\":3}");
+        assert!(resp.status == 401);
+        // nor replayed ones
+        let stale = (Utc::now().timestamp() - 3600).to_string();
+        let (signature, body) = signed(&json!({"type": 1}), &stale);
+        assert!(
+            interactions
+                .handle(Some(&signature), Some(&stale), &body)
+                .status
+                == 401
+        );
+
+        let (signature, body) = signed(&click("pause:10", "alice", &[]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["content"] == "debian.iso paused by <@alice>");
+
+        // neither the requester nor an admin
+        let (signature, body) = signed(&click("cancel:10", "bob", &["users"]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["flags"] == 64);
+        // denied, be it the requester or an admin
+        for (user, roles) in [("mallory", vec!["ops"]), ("alice", vec!["kids"])] {
+            let (signature, body) = signed(&click("cancel:10", user, &roles), &now);
+            let resp = interactions.handle(Some(&signature), timestamp, &body);
+            assert!(
+                resp.body["data"]["content"] == format!("Sorry <@{user}>, you may not do that")
+            );
+        }
+
+        let (signature, body) = signed(&click("delete:10", "carol", &["ops"]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["content"] == "debian.iso deleted with its files by <@carol>");
+        assert!(
+            *controller.done.borrow()
+                == ["pause dbid_1", "delete dbid_1", "rm /downloads/debian.iso"]
+        );
+
+        // organised downloads are deleted where they were moved to
+        let (signature, body) = signed(&click("delete:12", "alice", &[]), &now);
+        interactions.handle(Some(&signature), timestamp, &body);
+        assert!(controller.done.take()[3..] == ["delete dbid_2", "rm /video/Debian 12.iso"]);
+        // nothing is touched when the files cannot be told
+        let (signature, body) = signed(&click("delete:13", "alice", &[]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["flags"] == 64);
+        assert!(controller.done.take().is_empty());
+
+        let (signature, body) = signed(&click("pause:14", "alice", &[]), &now);
+        interactions.handle(Some(&signature), timestamp, &body);
+        assert!(controller.done.take() == ["pause dbid_2"]);
+        let (signature, body) = signed(&click("pause:15", "alice", &[]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["content"] == "This download is no longer in Download Station");
+        assert!(controller.done.take().is_empty());
+
+        let (signature, body) = signed(&click("resume:11", "alice", &[]), &now);
+        let resp = interactions.handle(Some(&signature), timestamp, &body);
+        assert!(resp.body["data"]["content"] == "This download is not followed anymore");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,37 +1,373 @@
+mod api;
+mod chat;
+mod cli;
 mod conf;
 mod core;
+mod interactions;
 mod logger;
 mod services;
-use core::traits::{DownloadingController, MessagingController};
-use log::{info, LevelFilter};
+use api::ApiServer;
+use chat::Reply;
+use chrono::Utc;
+use conf::{Command, Conf};
+use core::audit::AuditLog;
+use core::cleanup;
+use core::destination;
+use core::history::History;
+use core::organize;
+use core::quota;
+use core::rss::Seen;
+use core::search::Searches;
+use core::seeding;
+use core::speed::Turbo;
+use core::task::{RemoteTask, Task, TaskStatus};
+use core::traits::{DownloadingController, MessagingController, StorageController};
+use core::units::human_size;
+use interactions::Interactions;
+use log::{error, info, LevelFilter};
 use logger::SimpleLogger;
 use services::{
     discord::{DiscordController, DiscordService},
-    download_station::{DsControler, DsService},
+    download_station::{DsBackends, DsControler, DsService},
+    feeds::FeedWatcher,
+    hooks::Hooks,
 };
-use std::{thread, time::Duration};
+use std::{collections::BTreeMap, process, thread, time::Duration};
 
 static LOGGER: SimpleLogger = SimpleLogger;
 const REFRESH_TIME: Duration = Duration::from_secs(10);
 
 fn main() {
     let _ = log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Debug));
+    let conf = match Conf::load() {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("Invalid configuration: {e}");
+            process::exit(2);
+        }
+    };
+
+    match &conf.command {
+        None | Some(Command::Run) => run(&conf),
+        Some(Command::Serve) => serve(&conf),
+        Some(command) => {
+            // keep stdout clean for the command output
+            log::set_max_level(LevelFilter::Warn);
+            let synology = match conf.selected_synology() {
+                Ok(synology) => synology,
+                Err(e) => {
+                    eprintln!("{e}");
+                    process::exit(1);
+                }
+            };
+            let download_station = DsControler::<DsService>::new(synology);
+            if let Err(e) = cli::execute(&download_station, command, &conf) {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn run(conf: &Conf) {
     info!("DS-Companion starting");
-    let discord = DiscordController::<DiscordService>::new();
+    let discord_conf = match conf.discord() {
+        Ok(discord_conf) => discord_conf,
+        Err(e) => {
+            error!("Cannot fetch tasks from discord: {e}");
+            process::exit(1);
+        }
+    };
+
+    let discord = DiscordController::<DiscordService>::new(discord_conf);
     let mut tasks = discord.fetch_tasks().unwrap();
+    if !conf.rss.is_empty() {
+        let seen = Seen::load(conf.state_file.with_extension("rss.json"));
+        let mut watcher = FeedWatcher::new(conf.rss.clone(), seen);
+        for (feed, item) in watcher.poll() {
+            tasks.extend(discord.feed_task(&feed, &item));
+        }
+    }
 
     info!("Found {} new download tasks. Proceeding", tasks.len());
-    if tasks.len() > 0 {
-        let download_station = DsControler::<DsService>::new();
-        for task in &mut tasks {
-            download_station.submit_task(task);
+    let backends = DsBackends::<DsService>::new(conf.synology.clone(), conf.default_nas.clone());
+    let mut searches = Searches::load(conf.state_file.with_extension("search.json"));
+    let mut turbo = Turbo::load(conf.state_file.with_extension("turbo.json"));
+    for boost in turbo.due(Utc::now().timestamp()) {
+        let restored = match backends.get(&boost.nas) {
+            Some(download_station) => boost.restore(download_station),
+            None => Err(format!("unknown synology {}", boost.nas)),
+        };
+        match restored {
+            Ok(()) => {
+                info!("Turbo of {} over, speed limits restored", boost.nas);
+                turbo.stop(&boost.nas);
+                let message = format!("Turbo over, speed limits: {}", boost.limits);
+                discord.reply(&boost.command(), &message);
+            }
+            // tried again on the next run
+            Err(e) => error!("Could not restore the speed limits of {}: {e}", boost.nas),
+        }
+    }
+    for command in discord.fetch_commands() {
+        let nas = command.nas.as_deref().unwrap_or(backends.default_name());
+        let reply = match backends.get(nas) {
+            Some(download_station) => {
+                chat::execute(download_station, &command, nas, &mut turbo, &mut searches)
+            }
+            None => Reply::Text(format!("Unknown synology {nas}")),
+        };
+        match reply {
+            Reply::Text(content) => discord.reply(&command, &content),
+            Reply::Results(search) => discord.offer(&command, &search),
+            // submitted with the posted tasks
+            Reply::Download(result) => match discord.chosen_task(&command, &result) {
+                Some(task) => tasks.push(task),
+                None => discord.reply(&command, &format!("Cannot download {}", result.title)),
+            },
+        }
+    }
+    turbo.save();
+    searches.save(Utc::now().timestamp());
+    let mut history = History::load(conf.state_file.clone());
+    let audit = AuditLog::new(conf.access.audit_log.clone());
+    let hooks = Hooks::new(conf.hooks.clone());
+    // tasks are followed by the backend they were submitted to
+    let mut followed: BTreeMap<String, Vec<Task>> = BTreeMap::new();
+    // what each backend is downloading, fetched once to tell duplicates
+    let mut remote_tasks: BTreeMap<String, Vec<RemoteTask>> = BTreeMap::new();
+    // high priority tasks are submitted first
+    tasks.sort_by_key(|task| std::cmp::Reverse(task.request.priority));
+    for mut task in tasks.drain(..) {
+        let nas = task
+            .nas
+            .clone()
+            .unwrap_or(backends.default_name().to_string());
+        if let Some(download_station) = backends.get(&nas) {
+            let remote = remote_tasks
+                .entry(nas.clone())
+                .or_insert_with(|| download_station.list_tasks().unwrap_or_default());
+            if let Some(reply) = history.duplicate(&task, remote) {
+                info!("Task {} is a duplicate: {reply}", task.message_id);
+                let message = format!("{reply} <@{}>", task.user_id);
+                task.notifier.update_task_status(&mut task, Some(&message));
+                continue;
+            }
+        }
+        if let Some(reason) = refusal(conf, &task) {
+            info!("Task {} refused: {reason}", task.message_id);
+            task.set_status_and_notify(
+                TaskStatus::FAILED,
+                &format!("{reason} <@{}>", task.user_id),
+            );
+            continue;
+        }
+        let limits = conf.quotas.limits_for(&task.user_id);
+        let size = task.size.unwrap_or(0);
+        let now = Utc::now().timestamp();
+        if let Err(reason) = quota::check(limits, &task.user_id, size, &history, now) {
+            info!(
+                "Task {} exceeds the quota of {}: {reason}",
+                task.message_id, task.user_id
+            );
+            audit.record("quota", &task, &reason);
+            task.set_status_and_notify(
+                TaskStatus::FAILED,
+                &format!("FAILED: quota exceeded, {reason} <@{}>", task.user_id),
+            );
+            continue;
         }
+        match backends.get(&nas) {
+            Some(download_station) => {
+                if let Some(destination) = &task.destination_folder {
+                    if let Err(reason) =
+                        destination::check(download_station, destination, conf.create_folders)
+                    {
+                        info!("Task {} rejected: {reason}", task.message_id);
+                        task.set_status_and_notify(
+                            TaskStatus::FAILED,
+                            &format!("FAILED: {reason} <@{}>", task.user_id),
+                        );
+                        continue;
+                    }
+                }
+                task.nas = Some(nas.clone());
+                download_station.submit_task(&mut task);
+                if task.get_status() != TaskStatus::FAILED {
+                    history.record(&task);
+                }
+                followed.entry(nas).or_default().push(task);
+            }
+            None => {
+                error!("Task {} targets unknown synology {nas}", task.message_id);
+                task.set_status(TaskStatus::FAILED);
+            }
+        }
+    }
+    history.save();
 
-        while tasks.is_empty() == false {
-            thread::sleep(REFRESH_TIME);
-            download_station.get_jobs_advancement(&mut tasks);
+    while followed.values().any(|tasks| !tasks.is_empty()) {
+        thread::sleep(REFRESH_TIME);
+        for (nas, tasks) in followed.iter_mut().filter(|(_, t)| !t.is_empty()) {
+            // other runs may have subscribed users to our tasks
+            for task in tasks.iter_mut() {
+                for user in history.subscribers(&task.message_id) {
+                    if !task.request.notify.contains(user) {
+                        task.request.notify.push(user.clone());
+                    }
+                }
+            }
+            let download_station = backends.get(nas).unwrap();
+            let was_done: Vec<bool> = tasks
+                .iter()
+                .map(|task| task.get_status() == TaskStatus::DONE)
+                .collect();
+            download_station.get_jobs_advancement(tasks);
+            let mut remote = vec![];
+            // downloaded torrents seed until their policy is met, others
+            // are organised then may be removed from the list right away
+            if tasks
+                .iter()
+                .any(|task| task.get_status() == TaskStatus::DONE)
+            {
+                remote = download_station.list_tasks().unwrap_or_default();
+                for task in tasks.iter_mut().filter(|task| task.is_seeding()) {
+                    seeding::enforce(download_station, task, &remote, conf.remove_finished);
+                }
+                let over =
+                    |task: &Task| task.get_status() == TaskStatus::DONE && !task.is_seeding();
+                for task in tasks.iter_mut().filter(|task| over(task)) {
+                    organize_download(conf, download_station, task, &remote);
+                }
+                if conf.remove_finished {
+                    for task in tasks
+                        .iter()
+                        .filter(|task| over(task) && task.request.seed.is_none())
+                    {
+                        cleanup::remove(download_station, task, &remote);
+                    }
+                }
+            }
+            for (task, was_done) in tasks.iter_mut().zip(was_done) {
+                if was_done || task.get_status() != TaskStatus::DONE {
+                    continue;
+                }
+                // where Download Station put it, else where it was
+                // asked or routed to
+                let destination = remote
+                    .iter()
+                    .find(|r| task.source.matches(r.uri.as_deref(), Some(&r.title)))
+                    .and_then(|r| r.destination.clone())
+                    .or(task.destination_folder.clone());
+                let outcomes = hooks.run(task, destination.as_deref());
+                if !outcomes.is_empty() {
+                    let message = format!("HOOKS: {}", outcomes.join(", "));
+                    task.notifier.update_task_status(task, Some(&message));
+                }
+            }
+            tasks.iter().for_each(|task| history.record(task));
+            tasks.retain(|task| task.is_active() || task.is_seeding());
         }
+        history.save();
     }
 
     info!("DS-Companion exiting gracefully");
 }
+
+// Organise a finished download as its request and the organize rules
+// say, telling how it went
+fn organize_download<T: StorageController>(
+    conf: &Conf,
+    storage: &T,
+    task: &mut Task,
+    remote: &[RemoteTask],
+) {
+    let remote = match remote
+        .iter()
+        .find(|r| task.source.matches(r.uri.as_deref(), Some(&r.title)))
+    {
+        Some(remote) => remote,
+        None => return,
+    };
+    let rule = conf
+        .organize
+        .iter()
+        .find(|rule| destination::contains(rule.folder.as_deref(), remote.destination.as_deref()));
+    if rule.is_none() && task.request.name.is_none() && !task.request.extract {
+        return;
+    }
+    let message = match organize::organize(storage, rule, task, remote) {
+        Ok((steps, _)) if steps.is_empty() => return,
+        Ok((steps, path)) => {
+            task.location = Some(path);
+            format!("ORGANIZED: {}", steps.join(", "))
+        }
+        Err(e) => {
+            error!("Could not organise task {}: {e}", task.message_id);
+            format!("ORGANIZING FAILED: {e} <@{}>", task.user_id)
+        }
+    };
+    task.notifier.update_task_status(task, Some(&message));
+}
+
+// Why a task must not be submitted, if so
+fn refusal(conf: &Conf, task: &Task) -> Option<String> {
+    let (size, max) = (task.size?, conf.max_download_size?);
+    (size > max.0).then(|| {
+        format!(
+            "FAILED: too big, {} (max {})",
+            human_size(size),
+            human_size(max.0)
+        )
+    })
+}
+
+fn serve(conf: &Conf) {
+    info!("DS-Companion starting");
+    let (listen, token) = match (&conf.api_listen, &conf.api_token) {
+        (Some(listen), Some(token)) => (listen, token.clone()),
+        _ => {
+            error!("--api-listen and --api-token are required to expose the REST API");
+            process::exit(1);
+        }
+    };
+    let synology = match conf.selected_synology() {
+        Ok(synology) => synology,
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    };
+    let download_station = DsControler::<DsService>::new(synology);
+    let nas = conf.nas.clone().unwrap_or(conf.default_nas.clone());
+    let api = ApiServer::new(&download_station, token).with_history(conf.state_file.clone(), nas);
+    let key = match &conf.discord_public_key {
+        Some(key) => key,
+        None => return api.serve(listen),
+    };
+    // the buttons act on the NAS the download was submitted to
+    let backends = DsBackends::<DsService>::new(conf.synology.clone(), conf.default_nas.clone());
+    let controllers = conf
+        .synology
+        .keys()
+        .filter_map(|name| Some((name.clone(), backends.get(name)?)))
+        .collect();
+    let interactions = match Interactions::new(
+        key,
+        controllers,
+        conf.default_nas.clone(),
+        conf.state_file.clone(),
+        conf.access.clone(),
+    ) {
+        Ok(interactions) => interactions,
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    };
+    info!("Handling the buttons of the status messages on {listen}/interactions");
+    api.with_interactions(|signature, timestamp, body| {
+        interactions.handle(signature, timestamp, body)
+    })
+    .serve(listen);
+}
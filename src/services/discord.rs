@@ -1,9 +1,17 @@
-use std::io::Cursor;
-use std::str::FromStr;
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-use crate::conf::CONF;
+use crate::conf::{ChannelConf, DiscordConf, Rejection, RssConf};
+use crate::core::audit::AuditLog;
+use crate::core::command::{ChatCommand, TaskAction};
+use crate::core::magnet::Magnet;
+use crate::core::request::Request;
+use crate::core::rss::FeedItem;
+use crate::core::search::{Search, SearchResult};
 use crate::core::task::{Source, Task, TaskStatus};
+use crate::core::torrent::Torrent;
 use crate::core::traits::{HTTPService, MessagingController, Payload};
+use crate::core::units::human_size;
 use bytes::Bytes;
 use chrono::{DateTime, TimeDelta, Utc};
 use log::{debug, error, warn};
@@ -18,10 +26,23 @@ use super::schemas::AttachementObject;
 use super::API_USER_AGENT;
 
 const BASE_URL: &str = "https://discord.com/api/v10";
+const REJECTION_EMOJI: &str = "⛔";
+// Longer selections are summed up in the status embed
+const MAX_LISTED_FILES: usize = 20;
+const MAX_MESSAGE_LENGTH: usize = 2000;
+// Message components
+const ACTION_ROW: u8 = 1;
+const BUTTON: u8 = 2;
+const BUTTON_SECONDARY: u8 = 2;
+const BUTTON_DANGER: u8 = 4;
 
 #[derive(Default)]
 pub struct DiscordController<T> {
     service: T,
+    conf: DiscordConf,
+    audit: AuditLog,
+    // member roles, by channel_id/user_id
+    roles: RefCell<HashMap<String, Vec<String>>>,
 }
 
 #[derive(Default)]
@@ -30,13 +51,15 @@ pub struct DiscordService {
 }
 
 impl HTTPService for DiscordService {
-    fn new() -> Self {
+    type Conf = DiscordConf;
+
+    fn new(conf: &DiscordConf) -> Self {
         let client = Client::builder()
             .default_headers(
                 [
                     (
                         AUTHORIZATION,
-                        format!("Bot {}", CONF.discord_token).parse().unwrap(),
+                        format!("Bot {}", conf.token).parse().unwrap(),
                     ),
                     (USER_AGENT, header::HeaderValue::from_static(API_USER_AGENT)),
                     (
@@ -103,7 +126,8 @@ impl HTTPService for DiscordService {
         match resp {
             Ok(resp) => {
                 if resp.status().as_u16() < 300 {
-                    return Some(resp.json().unwrap());
+                    // some endpoints (e.g. reactions) answer 204 without body
+                    return Some(resp.json().unwrap_or(Value::Null));
                 } else {
                     warn!("Could not request {}. response: ", url_log);
                     return None;
@@ -117,12 +141,15 @@ impl HTTPService for DiscordService {
     }
 }
 
-fn _resp_to_task<T: HTTPService>(
+// A task, along with the reason why the message is invalid if so
+fn _resp_to_task<'a, T: HTTPService>(
     obj: serde_json::Value,
-    notifier: &DiscordController<T>,
-) -> Option<Task> {
+    notifier: &'a DiscordController<T>,
+    conf: &DiscordConf,
+    channel: &ChannelConf,
+) -> Option<(Task<'a>, Option<String>)> {
     let o = obj.as_object().unwrap();
-    let after: chrono::DateTime<Utc> = Utc::now() - TimeDelta::minutes(CONF.minutes_delta as i64);
+    let after: chrono::DateTime<Utc> = Utc::now() - TimeDelta::minutes(conf.minutes_delta as i64);
     if DateTime::parse_from_str(o["timestamp"].as_str().unwrap(), "%+").unwrap() > after {
         let id = String::from(o["id"].as_str().unwrap());
         let user_id = String::from(o["author"]["id"].as_str().unwrap());
@@ -130,9 +157,17 @@ fn _resp_to_task<T: HTTPService>(
 
         // content parsing
         let re_magnet = Regex::new(r"^(?<magnet>magnet:[^\n]+)").unwrap();
-        let re_destination = Regex::new(r"[t|T]o:\s*(?<path>[\w\/\s]*)\s*$").unwrap();
         let magnet_match = re_magnet.captures(&content);
-        let destination_match = re_destination.captures(&content);
+        let (request, mut invalid) = match Request::parse(&content) {
+            Ok(request) => (request, None),
+            Err(e) => (Request::default(), Some(e)),
+        };
+        let destination = request
+            .destination
+            .as_deref()
+            .map(|d| conf.router.resolve(d));
+        let nas = request.nas.clone().or(channel.nas.clone());
+        let seed = request.seed.clone().or(channel.seed.clone());
 
         // attachment extraction
         let attachment: Option<Bytes> = {
@@ -157,82 +192,349 @@ fn _resp_to_task<T: HTTPService>(
             }
         };
 
-        match magnet_match {
-            Some(magnet) => {
-                return Some(Task::new(
-                    Source::MAGNET(String::from(magnet["magnet"].trim())),
-                    id,
-                    notifier,
-                    if destination_match.is_some() {
-                        Some(String::from(&destination_match.unwrap()["path"]))
-                    } else {
-                        None
-                    },
-                    user_id,
-                ))
-            }
+        let source = match magnet_match {
+            Some(magnet) => match Magnet::parse(&magnet["magnet"]) {
+                Ok(magnet) => Source::MAGNET(magnet.to_string()),
+                Err(e) => {
+                    invalid = invalid.or(Some(e));
+                    Source::MAGNET(String::from(magnet["magnet"].trim()))
+                }
+            },
             None => match attachment {
                 Some(attachment) => {
-                    return Some(Task::new(
-                        Source::FILE(attachment),
-                        id,
-                        notifier,
-                        if destination_match.is_some() {
-                            Some(String::from(&destination_match.unwrap()["path"]))
-                        } else {
-                            None
-                        },
-                        user_id,
-                    ));
+                    if let Err(e) = Torrent::parse(&attachment) {
+                        invalid = invalid.or(Some(e));
+                    }
+                    Source::FILE(attachment)
                 }
                 None => {
                     warn!("No magnet link not .torrent found in message");
                     return None;
                 }
             },
-        }
+        };
+        // fallback on the routing rules, then on the channel's default destination
+        let destination = destination.or(conf.router.route(&source)).or(channel
+            .destination
+            .as_deref()
+            .map(|d| conf.router.resolve(d)));
+        let mut task = Task::new(
+            source,
+            id,
+            channel.id.clone(),
+            notifier,
+            destination,
+            user_id,
+        );
+        task.nas = nas;
+        task.request = request;
+        task.request.seed = seed;
+        task.size = match &task.source {
+            Source::FILE(_) => task.source.torrent().map(|t| t.size),
+            _ => task.source.magnet().and_then(|m| m.size),
+        };
+        Some((task, invalid))
     } else {
         return None;
     }
 }
 
-impl<T: HTTPService> MessagingController for DiscordController<T> {
-    fn new() -> Self
-    where
-        Self: Sized,
-        T: Sized,
-    {
-        let service = T::new();
-        return Self { service };
+impl<T: HTTPService<Conf = DiscordConf>> DiscordController<T> {
+    pub fn new(conf: DiscordConf) -> Self {
+        let service = T::new(&conf);
+        let audit = AuditLog::new(conf.access.audit_log.clone());
+        Self {
+            service,
+            conf,
+            audit,
+            roles: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Announce a new item of a watched feed in its channel, the task
+    // then follows that message as it does for the posted ones
+    pub fn feed_task(&self, feed: &RssConf, item: &FeedItem) -> Option<Task<'_>> {
+        // nothing is announced for links that cannot be downloaded
+        if let Err(e) = Source::from_uri(&item.link) {
+            warn!("Skipping {} of feed {}: {e}", item.title, feed.url);
+            return None;
+        }
+        let body =
+            json!({"content": format!("RSS: {}", item.title), "allowed_mentions": {"parse": []}});
+        let url =
+            Url::parse(format!("{BASE_URL}/channels/{}/messages", feed.channel).as_str()).unwrap();
+        let message_id = match self.service.send_request(
+            url,
+            Method::POST,
+            Some(Payload::BODY(Body::from(body.to_string()))),
+        ) {
+            Some(resp) if resp["id"].is_string() => resp["id"].as_str().unwrap().to_string(),
+            _ => {
+                error!(
+                    "Could not announce {} in channel_id {}",
+                    item.title, feed.channel
+                );
+                return None;
+            }
+        };
+        self.link_task(
+            &feed.channel,
+            message_id,
+            feed.user.clone(),
+            &item.link,
+            feed.destination.as_deref(),
+        )
+    }
+
+    // The search result picked with a command, followed in its thread
+    pub fn chosen_task(&self, command: &ChatCommand, result: &SearchResult) -> Option<Task<'_>> {
+        let mut task = self.link_task(
+            &command.channel_id,
+            command.message_id.clone(),
+            command.user_id.clone(),
+            &result.uri,
+            None,
+        )?;
+        task.size = task.size.or(Some(result.size).filter(|size| *size > 0));
+        Some(task)
+    }
+
+    // A task downloading a link as if it had been posted in the channel
+    fn link_task(
+        &self,
+        channel_id: &str,
+        message_id: String,
+        user_id: String,
+        link: &str,
+        destination: Option<&str>,
+    ) -> Option<Task<'_>> {
+        let source = match Source::from_uri(link) {
+            Ok(source) => source,
+            Err(e) => {
+                warn!("Cannot download {link}: {e}");
+                return None;
+            }
+        };
+        let channel = self.conf.channels.iter().find(|c| c.id == channel_id)?;
+        let destination = destination
+            .map(|d| self.conf.router.resolve(d))
+            .or(self.conf.router.route(&source))
+            .or(channel
+                .destination
+                .as_deref()
+                .map(|d| self.conf.router.resolve(d)));
+        let mut task = Task::new(
+            source,
+            message_id,
+            channel.id.clone(),
+            self,
+            destination,
+            user_id,
+        );
+        task.nas = channel.nas.clone();
+        task.request.seed = channel.seed.clone();
+        task.size = task.source.magnet().and_then(|m| m.size);
+        Some(task)
+    }
+}
+
+fn in_allowlist(
+    users: &[String],
+    roles: &[String],
+    user_id: &str,
+    member_roles: &[String],
+) -> bool {
+    (users.is_empty() && roles.is_empty())
+        || users.iter().any(|u| u == user_id)
+        || member_roles.iter().any(|r| roles.contains(r))
+}
+
+impl<T: HTTPService> DiscordController<T> {
+    // Whether the author of a message may start downloads from
+    // this channel, with the reason of the refusal otherwise
+    fn check_access(&self, channel: &ChannelConf, user_id: &str) -> Result<(), &'static str> {
+        let access = &self.conf.access;
+        if access.denied_users.iter().any(|u| u == user_id) {
+            return Err("user is denied");
+        }
+        let needs_roles = !access.denied_roles.is_empty()
+            || !access.allowed_roles.is_empty()
+            || !channel.allowed_roles.is_empty();
+        let roles = if needs_roles {
+            self.member_roles(&channel.id, user_id)
+        } else {
+            vec![]
+        };
+        if roles.iter().any(|r| access.denied_roles.contains(r)) {
+            return Err("role is denied");
+        }
+        if !in_allowlist(
+            &access.allowed_users,
+            &access.allowed_roles,
+            user_id,
+            &roles,
+        ) {
+            return Err("not in the allowlist");
+        }
+        if !in_allowlist(
+            &channel.allowed_users,
+            &channel.allowed_roles,
+            user_id,
+            &roles,
+        ) {
+            return Err("not in the channel allowlist");
+        }
+        Ok(())
     }
 
+    fn is_admin(&self, channel: &ChannelConf, user_id: &str) -> bool {
+        let access = &self.conf.access;
+        access.admin_users.iter().any(|u| u == user_id)
+            || (!access.admin_roles.is_empty()
+                && self
+                    .member_roles(&channel.id, user_id)
+                    .iter()
+                    .any(|r| access.admin_roles.contains(r)))
+    }
+
+    fn reject(&self, task: &mut Task, reason: &str) {
+        warn!(
+            "User {} is not allowed to download from channel_id {}: {reason}",
+            task.user_id, task.channel_id
+        );
+        self.audit.record("rejected", task, reason);
+        match self.conf.access.rejection {
+            Rejection::Reply => self.update_task_status(
+                task,
+                Some(&format!(
+                    "Sorry <@{}>, you are not allowed to start downloads here",
+                    task.user_id
+                )),
+            ),
+            Rejection::Reaction => self.react(task, REJECTION_EMOJI),
+            Rejection::Ignore => {}
+        }
+    }
+
+    fn react(&self, task: &Task, emoji: &str) {
+        let url = Url::parse(
+            format!(
+                "{BASE_URL}/channels/{}/messages/{}/reactions/{}/@me",
+                task.channel_id,
+                task.message_id,
+                urlencoding::encode(emoji)
+            )
+            .as_str(),
+        )
+        .unwrap();
+        if self.service.send_request(url, Method::PUT, None).is_none() {
+            error!("Could not react to message_id: {}", task.message_id);
+        }
+    }
+
+    // Roles are not part of channel messages, they have to be
+    // fetched from the guild the channel belongs to
+    fn member_roles(&self, channel_id: &str, user_id: &str) -> Vec<String> {
+        let key = format!("{channel_id}/{user_id}");
+        if let Some(roles) = self.roles.borrow().get(&key) {
+            return roles.clone();
+        }
+        let roles = self.fetch_member_roles(channel_id, user_id);
+        self.roles.borrow_mut().insert(key, roles.clone());
+        roles
+    }
+
+    fn fetch_member_roles(&self, channel_id: &str, user_id: &str) -> Vec<String> {
+        let url = Url::parse(format!("{BASE_URL}/channels/{channel_id}").as_str()).unwrap();
+        let guild_id = match self.service.send_request(url, Method::GET, None) {
+            Some(channel) => match channel["guild_id"].as_str() {
+                Some(guild_id) => guild_id.to_string(),
+                None => return vec![],
+            },
+            None => {
+                error!("Could not get guild of channel_id {channel_id}");
+                return vec![];
+            }
+        };
+        let url =
+            Url::parse(format!("{BASE_URL}/guilds/{guild_id}/members/{user_id}").as_str()).unwrap();
+        match self.service.send_request(url, Method::GET, None) {
+            Some(member) => member["roles"]
+                .as_array()
+                .map(|roles| {
+                    roles
+                        .iter()
+                        .filter_map(|r| r.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => {
+                error!("Could not get roles of user {user_id} in guild {guild_id}");
+                vec![]
+            }
+        }
+    }
+}
+
+impl<T: HTTPService> MessagingController for DiscordController<T> {
     fn update_task_status(&self, task: &mut Task, message: Option<&str>) {
         let content = if message.is_none() {
+            let mut status = task.get_status().to_string();
+            if let Some(name) = task.source.name() {
+                status += &format!(": {name}");
+            }
+            if task.get_status() == TaskStatus::SUBMITTED {
+                if let Some(torrent) = task.source.torrent() {
+                    status += &format!(
+                        ", {} in {} file(s)",
+                        human_size(torrent.size),
+                        torrent.files.len()
+                    );
+                }
+                let trackers = task.source.tracker_hosts();
+                if !trackers.is_empty() {
+                    status += &format!(" (trackers: {})", trackers.join(", "));
+                }
+            }
             if task.get_status() == TaskStatus::DONE || task.get_status() == TaskStatus::FAILED {
-                task.get_status().to_string()
-                    + &String::from_str(&format!(" <@{}>", task.user_id)).unwrap()
+                std::iter::once(&task.user_id)
+                    .chain(task.request.notify.iter())
+                    .fold(status, |content, user| content + &format!(" <@{user}>"))
             } else {
-                task.get_status().to_string()
+                status
             }
         } else {
             message.unwrap().to_string()
         };
-        let body = json!({"content":content, "message_reference":{"message_id":task.message_id}, "allowed_mentions": {"users": [task.user_id]}});
+        let mut body = json!({"content":content, "message_reference":{"message_id":task.message_id}, "allowed_mentions": {"users": std::iter::once(&task.user_id).chain(task.request.notify.iter()).collect::<Vec<_>>()}});
+        if let (TaskStatus::SUBMITTED, Some(files)) = (task.get_status(), &task.selected_files) {
+            body["embeds"] = json!([selection_embed(files)]);
+        }
+        if self.conf.buttons && message.is_none() {
+            let actions = match task.get_status() {
+                TaskStatus::SUBMITTED | TaskStatus::DOWNLOADING => &TaskAction::ALL[..],
+                TaskStatus::DONE if !self.conf.remove_finished => &[TaskAction::DELETE][..],
+                _ => &[][..],
+            };
+            if !actions.is_empty() {
+                body["components"] = json!([buttons(actions, &task.message_id)]);
+            }
+        }
 
-        let cursor = Cursor::new(body.to_string());
-        let url =
-            Url::parse(format!("{BASE_URL}/channels/{}/messages", CONF.discord_channel).as_str())
-                .unwrap();
+        let url = Url::parse(format!("{BASE_URL}/channels/{}/messages", task.channel_id).as_str())
+            .unwrap();
 
-        let resp =
-            self.service
-                .send_request(url, Method::POST, Some(Payload::BODY(Body::new(cursor))));
+        let resp = self.service.send_request(
+            url,
+            Method::POST,
+            Some(Payload::BODY(Body::from(body.to_string()))),
+        );
 
         match resp {
             Some(res) => {
                 debug!(
                     "Response received from channel {}: {:?}",
-                    CONF.discord_channel, res
+                    task.channel_id, res
                 );
             }
             None => {
@@ -242,56 +544,221 @@ impl<T: HTTPService> MessagingController for DiscordController<T> {
     }
 
     fn fetch_tasks(&self) -> Option<Vec<Task>> {
-        let url =
-            Url::parse(format!("{BASE_URL}/channels/{}/messages", CONF.discord_channel).as_str())
-                .unwrap();
+        let mut tasks: Vec<Task> = vec![];
+        let mut reachable = false;
 
-        let resp = self.service.send_request(url, Method::GET, None);
-        match resp {
-            Some(res) => {
-                let tasks: Vec<Task> = res
-                    .as_array()
-                    .unwrap()
-                    .iter()
-                    .map(|x| _resp_to_task(x.clone(), &self))
-                    .filter(|x| x.is_some())
-                    .map(|x| x.unwrap())
-                    .collect();
+        for channel in &self.conf.channels {
+            let url = Url::parse(format!("{BASE_URL}/channels/{}/messages", channel.id).as_str())
+                .unwrap();
 
-                return Some(tasks);
+            match self.service.send_request(url, Method::GET, None) {
+                Some(res) => {
+                    reachable = true;
+                    let candidates: Vec<(Task, Option<String>)> = res
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .filter_map(|x| _resp_to_task(x.clone(), self, &self.conf, channel))
+                        .collect();
+                    for (mut task, invalid) in candidates {
+                        match (self.check_access(channel, &task.user_id), invalid) {
+                            (Ok(()), Some(reason)) => {
+                                self.audit.record("invalid", &task, &reason);
+                                let message = format!("FAILED: {reason} <@{}>", task.user_id);
+                                task.set_status_and_notify(TaskStatus::FAILED, &message);
+                            }
+                            (Ok(()), None) => {
+                                self.audit.record("accepted", &task, "");
+                                if !task.request.unknown.is_empty() {
+                                    let message = format!(
+                                        "Unknown options ignored: {}",
+                                        task.request.unknown.join(", ")
+                                    );
+                                    self.update_task_status(&mut task, Some(&message));
+                                }
+                                tasks.push(task);
+                            }
+                            (Err(reason), _) => self.reject(&mut task, reason),
+                        }
+                    }
+                }
+                None => {
+                    error!(
+                        "Could not retrieve tasks from discord channel_id {}",
+                        channel.id
+                    );
+                }
             }
-            None => {
-                error!(
-                    "Could not retrieve tasks from discord channel_id {}",
-                    CONF.discord_channel
-                );
-                return None;
+        }
+
+        if reachable {
+            Some(tasks)
+        } else {
+            None
+        }
+    }
+
+    fn fetch_commands(&self) -> Vec<ChatCommand> {
+        let after: DateTime<Utc> = Utc::now() - TimeDelta::minutes(self.conf.minutes_delta as i64);
+        let mut commands = vec![];
+        for channel in &self.conf.channels {
+            let url = Url::parse(format!("{BASE_URL}/channels/{}/messages", channel.id).as_str())
+                .unwrap();
+            let messages = match self.service.send_request(url, Method::GET, None) {
+                Some(res) => res.as_array().cloned().unwrap_or_default(),
+                None => {
+                    error!(
+                        "Could not retrieve commands from discord channel_id {}",
+                        channel.id
+                    );
+                    continue;
+                }
+            };
+            for message in messages {
+                let recent = message["timestamp"]
+                    .as_str()
+                    .and_then(|t| DateTime::parse_from_str(t, "%+").ok())
+                    .is_some_and(|t| t > after);
+                let (name, args) = match message["content"].as_str().and_then(ChatCommand::parse) {
+                    Some(command) if recent => command,
+                    _ => continue,
+                };
+                let user_id = message["author"]["id"].as_str().unwrap_or_default();
+                if let Err(reason) = self.check_access(channel, user_id) {
+                    warn!(
+                        "User {user_id} may not run !{name} in channel_id {}: {reason}",
+                        channel.id
+                    );
+                    continue;
+                }
+                commands.push(ChatCommand {
+                    message_id: message["id"].as_str().unwrap_or_default().to_string(),
+                    channel_id: channel.id.clone(),
+                    user_id: user_id.to_string(),
+                    name,
+                    args,
+                    nas: channel.nas.clone(),
+                    admin: self.is_admin(channel, user_id),
+                });
             }
         }
+        commands
+    }
+
+    fn reply(&self, command: &ChatCommand, content: &str) {
+        // discord refuses longer messages
+        let content: String = content.chars().take(MAX_MESSAGE_LENGTH).collect();
+        let body = json!({"content": content, "message_reference": {"message_id": command.message_id}, "allowed_mentions": {"users": [command.user_id]}});
+        self.post_reply(command, body);
     }
+
+    fn offer(&self, command: &ChatCommand, search: &Search) {
+        let body = json!({"message_reference": {"message_id": command.message_id}, "embeds": [results_embed(search)]});
+        self.post_reply(command, body);
+    }
+}
+
+impl<T: HTTPService> DiscordController<T> {
+    fn post_reply(&self, command: &ChatCommand, body: Value) {
+        let url =
+            Url::parse(format!("{BASE_URL}/channels/{}/messages", command.channel_id).as_str())
+                .unwrap();
+        let resp = self.service.send_request(
+            url,
+            Method::POST,
+            Some(Payload::BODY(Body::from(body.to_string()))),
+        );
+        if resp.is_none() {
+            error!("Could not reply to message_id: {}", command.message_id);
+        }
+    }
+}
+
+// An action row acting on the download of message_id
+fn buttons(actions: &[TaskAction], message_id: &str) -> Value {
+    let components: Vec<Value> = actions
+        .iter()
+        .map(|action| {
+            // red for what cannot be undone
+            let style = match action {
+                TaskAction::CANCEL | TaskAction::DELETE => BUTTON_DANGER,
+                _ => BUTTON_SECONDARY,
+            };
+            json!({"type": BUTTON, "style": style, "label": action.label(), "custom_id": action.custom_id(message_id)})
+        })
+        .collect();
+    json!({"type": ACTION_ROW, "components": components})
+}
+
+// Search results, numbered for !get
+fn results_embed(search: &Search) -> Value {
+    let description: Vec<String> = search
+        .results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| format!("**{}.** {}", i + 1, result.summary()))
+        .collect();
+    json!({
+        "title": format!("Results for {}", search.terms),
+        "description": description.join("\n"),
+        "footer": {"text": "Reply !get <number> to download one of them"}
+    })
+}
+
+// Files picked among the torrent's ones, listed under the status
+fn selection_embed(files: &[String]) -> Value {
+    let mut description: Vec<String> = files
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|file| format!("• {file}"))
+        .collect();
+    if files.len() > MAX_LISTED_FILES {
+        description.push(format!("… and {} more", files.len() - MAX_LISTED_FILES));
+    }
+    json!({"title": format!("{} file(s) selected", files.len()), "description": description.join("\n")})
 }
 
 /////Unit Tests/////
 
 #[cfg(test)]
 pub mod tests {
-    use std::sync::Mutex;
+    use std::{collections::BTreeMap, sync::Mutex};
 
     use bytes::Bytes;
     use reqwest::{Method, Url};
     use serde_json::{json, Value};
 
     use crate::{
-        core::task::Source,
+        conf::{AccessConf, ChannelConf, DiscordConf, Rejection, RouteConf, RssConf},
+        core::command::ChatCommand,
+        core::routing::Router,
+        core::rss::FeedItem,
+        core::search::{tests::result, Search},
+        core::task::{Source, Task, TaskStatus},
+        core::torrent::tests::torrent_file,
         core::traits::{HTTPService, MessagingController, Payload},
-        services::discord::DiscordController,
+        services::discord::{selection_embed, DiscordController},
     };
 
+    fn conf() -> DiscordConf {
+        DiscordConf {
+            token: "token".to_string(),
+            channels: vec![ChannelConf {
+                id: "1".to_string(),
+                ..Default::default()
+            }],
+            minutes_delta: 2,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn only_uses_magnet_links() {
         struct DiscordServiceMock {}
         impl HTTPService for DiscordServiceMock {
-            fn new() -> Self {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
                 Self {}
             }
             fn download_file(&self, _: Url) -> Option<Bytes> {
@@ -299,19 +766,25 @@ pub mod tests {
             }
             fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
                 return Some(json!([
-                    {"content": "magnet:aaaa", "id": "1","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
-                    {"content": "notmagnet:....", "id": "2","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
+                    {"content": "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "id": "1","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "notmagnet:....", "id": "2","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:aaaa", "id": "3","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
                 ]));
             }
         }
 
-        let controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DiscordController::<DiscordServiceMock>::new(conf());
         let mut tasks = controler.fetch_tasks().unwrap();
         assert!(tasks.len() == 1);
         let task = tasks.pop().unwrap();
 
         //task analysis
-        assert!(task.source == Source::MAGNET("magnet:aaaa".to_string()));
+        assert!(
+            task.source
+                == Source::MAGNET(
+                    "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string()
+                )
+        );
         assert!(task.user_id == "xxx")
     }
 
@@ -319,7 +792,9 @@ pub mod tests {
     fn load_tasks_posterior_to_datetime_delta() {
         struct DiscordServiceMock {}
         impl HTTPService for DiscordServiceMock {
-            fn new() -> Self {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
                 Self {}
             }
             fn download_file(&self, _: Url) -> Option<Bytes> {
@@ -327,24 +802,31 @@ pub mod tests {
             }
             fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
                 return Some(json!([
-                    {"content": "magnet:bbbb", "id": "3","timestamp": "2004-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
-                    {"content": "magnet:cccc  ", "id": "4","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", "id": "3","timestamp": "2004-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:cccccccccccccccccccccccccccccccccccccccc  ", "id": "4","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
                 ]));
             }
         }
-        let controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DiscordController::<DiscordServiceMock>::new(conf());
         let mut tasks = controler.fetch_tasks().unwrap();
         assert!(tasks.len() == 1);
         //task analysis
         let task = tasks.pop().unwrap();
-        assert!(task.source == Source::MAGNET("magnet:cccc".to_string())) // check properly trimed
+        assert!(
+            task.source
+                == Source::MAGNET(
+                    "magnet:?xt=urn:btih:cccccccccccccccccccccccccccccccccccccccc".to_string()
+                )
+        ) // check properly trimed
     }
 
     #[test]
     fn set_destination_folder() {
         struct DiscordServiceMock {}
         impl HTTPService for DiscordServiceMock {
-            fn new() -> Self {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
                 Self {}
             }
             fn download_file(&self, _: Url) -> Option<Bytes> {
@@ -352,15 +834,18 @@ pub mod tests {
             }
             fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
                 return Some(json!([
-                    {"content": "magnet:bbbb\nTo: videos/Movies", "id": "5","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
-                    {"content": "magnet:bbbb\nto:videos/Series", "id": "6","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
-                    {"content": "magnet:bbbb\nTo: videos/ Somewhere", "id": "7","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: Vidéos/Films (2024)", "id": "3","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: videos/../../etc", "id": "4","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: videos/Movies", "id": "5","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nto:videos/Series", "id": "6","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: videos/ Somewhere", "id": "7","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
                 ]));
             }
         }
-        let controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DiscordController::<DiscordServiceMock>::new(conf());
         let mut tasks = controler.fetch_tasks().unwrap();
-        assert!(tasks.len() == 3);
+        // the traversal attempt is refused
+        assert!(tasks.len() == 4);
         //task analysis
 
         let task = tasks.pop().unwrap();
@@ -371,6 +856,48 @@ pub mod tests {
 
         let task = tasks.pop().unwrap();
         assert!(task.destination_folder.unwrap() == "videos/Movies");
+
+        let task = tasks.pop().unwrap();
+        assert!(task.destination_folder.unwrap() == "Vidéos/Films (2024)");
+    }
+
+    #[test]
+    fn destination_aliases_and_routes() {
+        struct DiscordServiceMock {}
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {}
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                return Some(json!([
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: movies", "id": "5","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb&dn=Show.S01E01.720p", "id": "6","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}},
+                    {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb&dn=debian.iso", "id": "7","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"xxx"}}
+                ]));
+            }
+        }
+        let router = Router::new(
+            BTreeMap::from([(String::from("movies"), String::from("video/Movies"))]),
+            vec![RouteConf {
+                pattern: Some(String::from(r"S\d\dE\d\d")),
+                destination: String::from("video/Series"),
+                ..Default::default()
+            }],
+        )
+        .unwrap();
+        let controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf { router, ..conf() });
+        let mut tasks = controler.fetch_tasks().unwrap();
+        assert!(tasks.len() == 3);
+
+        assert!(tasks.pop().unwrap().destination_folder.is_none());
+        assert!(tasks.pop().unwrap().destination_folder.unwrap() == "video/Series");
+        assert!(tasks.pop().unwrap().destination_folder.unwrap() == "video/Movies");
     }
 
     #[test]
@@ -379,12 +906,14 @@ pub mod tests {
             time_called: Mutex<i8>,
         }
         impl HTTPService for DiscordServiceMock {
-            fn new() -> Self {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
                 let time_called: Mutex<i8> = Mutex::new(0);
                 Self { time_called }
             }
             fn download_file(&self, _: reqwest::Url) -> Option<Bytes> {
-                let file = Bytes::from("Hello world");
+                let file = Bytes::from(torrent_file());
                 return Some(file);
             }
             fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
@@ -415,11 +944,403 @@ pub mod tests {
             }
         }
 
-        let controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DiscordController::<DiscordServiceMock>::new(conf());
         let mut tasks = controler.fetch_tasks().unwrap();
         assert!(tasks.len() == 1);
 
         let task = tasks.pop().unwrap();
-        assert!(task.source == Source::FILE(Bytes::from("Hello world")))
+        assert!(task.source == Source::FILE(Bytes::from(torrent_file())));
+        assert!(task.size == Some(1536))
+    }
+
+    #[test]
+    fn per_channel_settings() {
+        struct DiscordServiceMock {}
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {}
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                match url.path() {
+                    "/api/v10/channels/movies/messages" => Some(json!([
+                        {"content": "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "id": "1","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"alice"}},
+                        {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\nTo: video/Series\nOn: office-nas", "id": "2","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"alice"}}
+                    ])),
+                    "/api/v10/channels/isos/messages" => Some(json!([
+                        {"content": "magnet:?xt=urn:btih:cccccccccccccccccccccccccccccccccccccccc", "id": "3","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"bob"}},
+                        {"content": "magnet:?xt=urn:btih:dddddddddddddddddddddddddddddddddddddddd", "id": "4","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"carol"}}
+                    ])),
+                    "/api/v10/channels/isos" => Some(json!({"id": "isos", "guild_id": "guild"})),
+                    "/api/v10/guilds/guild/members/bob" => Some(json!({"roles": ["admins"]})),
+                    "/api/v10/guilds/guild/members/carol" => Some(json!({"roles": ["guests"]})),
+                    _ => None,
+                }
+            }
+        }
+
+        let controler = DiscordController::<DiscordServiceMock>::new(DiscordConf {
+            token: "token".to_string(),
+            channels: vec![
+                ChannelConf {
+                    id: "movies".to_string(),
+                    destination: Some("video/Movies".to_string()),
+                    nas: Some("home-nas".to_string()),
+                    ..Default::default()
+                },
+                ChannelConf {
+                    id: "isos".to_string(),
+                    destination: Some("downloads/iso".to_string()),
+                    allowed_roles: vec!["admins".to_string()],
+                    ..Default::default()
+                },
+            ],
+            minutes_delta: 2,
+            ..Default::default()
+        });
+        let tasks = controler.fetch_tasks().unwrap();
+        assert!(tasks.len() == 3);
+
+        assert!(tasks[0].channel_id == "movies");
+        assert!(tasks[0].destination_folder.as_deref() == Some("video/Movies"));
+        assert!(tasks[0].nas.as_deref() == Some("home-nas"));
+        assert!(tasks[1].destination_folder.as_deref() == Some("video/Series"));
+        assert!(tasks[1].nas.as_deref() == Some("office-nas"));
+        // carol lacks the role
+        assert!(tasks[2].user_id == "bob");
+        assert!(tasks[2].channel_id == "isos");
+        assert!(tasks[2].destination_folder.as_deref() == Some("downloads/iso"));
+        assert!(tasks[2].nas.is_none());
+    }
+
+    #[test]
+    fn rejected_users() {
+        struct DiscordServiceMock {
+            requests: Mutex<Vec<(Method, String)>>,
+        }
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {
+                    requests: Mutex::new(vec![]),
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, url: Url, method: Method, _: Option<Payload>) -> Option<Value> {
+                self.requests
+                    .lock()
+                    .unwrap()
+                    .push((method.clone(), url.path().to_string()));
+                match url.path() {
+                    "/api/v10/channels/1/messages" if method == Method::GET => Some(json!([
+                        {"content": "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "id": "10","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"alice"}},
+                        {"content": "magnet:?xt=urn:btih:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", "id": "11","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"mallory"}},
+                        {"content": "magnet:?xt=urn:btih:cccccccccccccccccccccccccccccccccccccccc", "id": "12","timestamp": "2044-12-25T19:07:12.600000+00:00", "author":{"id":"bob"}}
+                    ])),
+                    "/api/v10/channels/1" => Some(json!({"id": "1", "guild_id": "guild"})),
+                    "/api/v10/guilds/guild/members/alice" => Some(json!({"roles": ["family"]})),
+                    "/api/v10/guilds/guild/members/bob" => {
+                        Some(json!({"roles": ["family", "kids"]}))
+                    }
+                    _ => Some(Value::Null),
+                }
+            }
+        }
+
+        let mut conf = conf();
+        conf.access = AccessConf {
+            denied_users: vec!["mallory".to_string()],
+            denied_roles: vec!["kids".to_string()],
+            allowed_roles: vec!["family".to_string()],
+            ..Default::default()
+        };
+        let controler = DiscordController::<DiscordServiceMock>::new(conf.clone());
+        let tasks = controler.fetch_tasks().unwrap();
+        assert!(tasks.len() == 1);
+        assert!(tasks[0].user_id == "alice");
+        drop(tasks);
+        let requests = controler.service.requests.lock().unwrap();
+        let replies = requests
+            .iter()
+            .filter(|(m, p)| *m == Method::POST && p == "/api/v10/channels/1/messages")
+            .count();
+        assert!(replies == 2);
+        // guild and roles are fetched once per user
+        assert!(
+            requests
+                .iter()
+                .filter(|(_, p)| p == "/api/v10/channels/1")
+                .count()
+                == 2
+        );
+        drop(requests);
+
+        conf.access.rejection = Rejection::Reaction;
+        let controler = DiscordController::<DiscordServiceMock>::new(conf);
+        let tasks = controler.fetch_tasks().unwrap();
+        assert!(tasks.len() == 1);
+        drop(tasks);
+        let requests = controler.service.requests.lock().unwrap();
+        assert!(requests.iter().any(|(m, p)| *m == Method::PUT
+            && p == "/api/v10/channels/1/messages/11/reactions/%E2%9B%94/@me"));
+    }
+
+    #[test]
+    fn selection_listing() {
+        let files: Vec<String> = (1..=25).map(|i| format!("Show/E{i:02}.mkv")).collect();
+        let embed = selection_embed(&files);
+        assert!(embed["title"] == "25 file(s) selected");
+        let description = embed["description"].as_str().unwrap();
+        assert!(description.starts_with("• Show/E01.mkv\n• Show/E02.mkv"));
+        assert!(description.ends_with("• Show/E20.mkv\n… and 5 more"));
+    }
+
+    #[test]
+    fn chat_commands() {
+        struct DiscordServiceMock {
+            replies: Mutex<Vec<Value>>,
+        }
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {
+                    replies: Mutex::new(vec![]),
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(
+                &self,
+                _: Url,
+                method: Method,
+                payload: Option<Payload>,
+            ) -> Option<Value> {
+                if method == Method::POST {
+                    let Some(Payload::BODY(body)) = payload else {
+                        panic!("Expected a body")
+                    };
+                    let body = serde_json::from_slice(body.as_bytes().unwrap()).unwrap();
+                    self.replies.lock().unwrap().push(body);
+                    return Some(json!({}));
+                }
+                Some(json!([
+                    {"content": "!Stats", "id": "10", "timestamp": "2044-12-25T19:07:12.600000+00:00", "author": {"id": "alice"}},
+                    {"content": "!stats", "id": "11", "timestamp": "2044-12-25T19:07:12.600000+00:00", "author": {"id": "mallory"}},
+                    {"content": "!stats", "id": "12", "timestamp": "2001-12-25T19:07:12.600000+00:00", "author": {"id": "alice"}},
+                    {"content": "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", "id": "13", "timestamp": "2044-12-25T19:07:12.600000+00:00", "author": {"id": "alice"}}
+                ]))
+            }
+        }
+
+        let mut conf = conf();
+        conf.channels[0].nas = Some(String::from("office-nas"));
+        conf.access.denied_users = vec![String::from("mallory")];
+        conf.access.admin_users = vec![String::from("alice")];
+        let controler = DiscordController::<DiscordServiceMock>::new(conf);
+        let commands = controler.fetch_commands();
+        assert!(commands.len() == 1);
+        assert!(commands[0].name == "stats" && commands[0].message_id == "10");
+        assert!(commands[0].nas.as_deref() == Some("office-nas"));
+        assert!(commands[0].admin);
+
+        controler.reply(&commands[0], &"a".repeat(3000));
+        let replies = controler.service.replies.lock().unwrap();
+        assert!(replies[0]["content"].as_str().unwrap().len() == 2000);
+        assert!(replies[0]["message_reference"]["message_id"] == "10");
+    }
+
+    #[test]
+    fn feed_announcement() {
+        struct DiscordServiceMock {
+            announced: Mutex<Vec<Value>>,
+        }
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {
+                    announced: Mutex::new(vec![]),
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, _: Url, _: Method, payload: Option<Payload>) -> Option<Value> {
+                let Some(Payload::BODY(body)) = payload else {
+                    panic!("Expected a body")
+                };
+                let body = serde_json::from_slice(body.as_bytes().unwrap()).unwrap();
+                self.announced.lock().unwrap().push(body);
+                Some(json!({"id": "99"}))
+            }
+        }
+
+        let mut conf = conf();
+        conf.channels[0].nas = Some(String::from("office-nas"));
+        conf.channels[0].destination = Some(String::from("downloads"));
+        conf.router = Router::new(
+            BTreeMap::from([(String::from("isos"), String::from("linux/isos"))]),
+            vec![],
+        )
+        .unwrap();
+        let controler = DiscordController::<DiscordServiceMock>::new(conf);
+        let feed = RssConf {
+            url: String::from("https://example.org/rss"),
+            channel: String::from("1"),
+            user: String::from("42"),
+            destination: Some(String::from("isos")),
+            ..Default::default()
+        };
+        let item = FeedItem {
+            id: String::from("deb-12.5"),
+            title: String::from("Debian 12.5"),
+            link: String::from("https://example.org/debian.torrent"),
+        };
+        let task = controler.feed_task(&feed, &item).unwrap();
+        assert!(task.message_id == "99" && task.channel_id == "1" && task.user_id == "42");
+        assert!(task.destination_folder.as_deref() == Some("linux/isos"));
+        assert!(task.nas.as_deref() == Some("office-nas"));
+        assert!(controler.service.announced.lock().unwrap()[0]["content"] == "RSS: Debian 12.5");
+
+        let item = FeedItem {
+            link: String::from("https://example.org/page"),
+            ..item
+        };
+        let feed = RssConf {
+            channel: String::from("unknown"),
+            ..feed
+        };
+        assert!(controler.feed_task(&feed, &item).is_none());
+    }
+
+    #[test]
+    fn search_offer() {
+        struct DiscordServiceMock {
+            posted: Mutex<Vec<Value>>,
+        }
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {
+                    posted: Mutex::new(vec![]),
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, _: Url, _: Method, payload: Option<Payload>) -> Option<Value> {
+                let Some(Payload::BODY(body)) = payload else {
+                    panic!("Expected a body")
+                };
+                let body = serde_json::from_slice(body.as_bytes().unwrap()).unwrap();
+                self.posted.lock().unwrap().push(body);
+                Some(json!({}))
+            }
+        }
+
+        let mut conf = conf();
+        conf.channels[0].destination = Some(String::from("downloads"));
+        let controler = DiscordController::<DiscordServiceMock>::new(conf);
+        let command = ChatCommand {
+            message_id: String::from("10"),
+            channel_id: String::from("1"),
+            user_id: String::from("42"),
+            name: String::from("search"),
+            ..Default::default()
+        };
+        let search = Search {
+            message_id: String::from("10"),
+            channel_id: String::from("1"),
+            user_id: String::from("42"),
+            terms: String::from("linux"),
+            results: vec![result("debian", 120), result("ubuntu", 80)],
+            searched_at: 0,
+        };
+        controler.offer(&command, &search);
+        let embed = &controler.service.posted.lock().unwrap()[0]["embeds"][0];
+        assert!(embed["title"] == "Results for linux");
+        assert!(
+            embed["description"]
+                == "**1.** debian, 1.5 GB, 120 seeders (linuxtracker)\n**2.** ubuntu, 1.5 GB, 80 seeders (linuxtracker)"
+        );
+
+        let task = controler.chosen_task(&command, &search.results[1]).unwrap();
+        assert!(task.message_id == "10" && task.user_id == "42");
+        assert!(task.source == Source::URL(String::from("https://example.org/ubuntu.torrent")));
+        assert!(task.destination_folder.as_deref() == Some("downloads"));
+        assert!(task.size == Some(1536 * 1024 * 1024));
+    }
+
+    #[test]
+    fn status_buttons() {
+        struct DiscordServiceMock {
+            posted: Mutex<Vec<Value>>,
+        }
+        impl HTTPService for DiscordServiceMock {
+            type Conf = DiscordConf;
+
+            fn new(_: &DiscordConf) -> Self {
+                Self {
+                    posted: Mutex::new(vec![]),
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+            fn send_request(&self, _: Url, _: Method, payload: Option<Payload>) -> Option<Value> {
+                let Some(Payload::BODY(body)) = payload else {
+                    panic!("Expected a body")
+                };
+                let body = serde_json::from_slice(body.as_bytes().unwrap()).unwrap();
+                self.posted.lock().unwrap().push(body);
+                Some(json!({}))
+            }
+        }
+
+        let labels = |body: &Value| -> Vec<String> {
+            body["components"][0]["components"]
+                .as_array()
+                .map(|buttons| {
+                    buttons
+                        .iter()
+                        .map(|b| b["label"].as_str().unwrap().to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        for remove_finished in [false, true] {
+            let mut conf = conf();
+            conf.buttons = true;
+            conf.remove_finished = remove_finished;
+            let controler = DiscordController::<DiscordServiceMock>::new(conf);
+            let mut task = Task::new(
+                Source::URL(String::from("https://example.org/debian.iso")),
+                String::from("10"),
+                String::from("1"),
+                &controler,
+                None,
+                String::from("42"),
+            );
+            task.set_status(TaskStatus::DOWNLOADING);
+            task.set_status(TaskStatus::DONE);
+            let posted = controler.service.posted.lock().unwrap();
+            assert!(labels(&posted[0]) == ["Pause", "Resume", "Cancel", "Delete files"]);
+            assert!(posted[0]["components"][0]["components"][0]["custom_id"] == "pause:10");
+            // the task is gone from Download Station once removed
+            match remove_finished {
+                true => assert!(labels(&posted[1]).is_empty()),
+                false => assert!(labels(&posted[1]) == ["Delete files"]),
+            }
+        }
     }
 }
@@ -1,25 +1,74 @@
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use crate::conf::CONF;
-use crate::core::task::{Source, Task, TaskStatus};
-use crate::core::traits::{DownloadingController, HTTPService, Payload};
-use crate::services::schemas::{InfoResponse, DS_ERROR_CODES};
+use crate::conf::SynologyConf;
+use crate::core::request::FileSelection;
+use crate::core::rss::{RssItem, RssSite};
+use crate::core::search::SearchResult;
+use crate::core::speed::SpeedLimits;
+use crate::core::stats::Statistics;
+use crate::core::task::{RemoteTask, Source, Task, TaskStatus};
+use crate::core::traits::{
+    DownloadingController, HTTPService, Payload, RssController, SearchController, SpeedController,
+};
+use crate::services::schemas::{DsTaskObject, InfoResponse, DS_ERROR_CODES};
 use crate::services::API_CONTENT_TYPE;
 use bytes::Bytes;
 use log::{debug, error, warn};
+use once_cell::unsync::OnceCell;
 
+use reqwest::blocking::multipart::{Form, Part};
 use reqwest::blocking::Client;
 use reqwest::header::{self, HeaderValue, ACCEPT, USER_AGENT};
 use reqwest::{Method, Url};
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use super::schemas::{ApiInformation, DS_TO_COMPANION_MAPPING};
 use super::API_USER_AGENT;
 
+// How long DownloadStation2 may take to list the files of a magnet
+const LIST_TIMEOUT: Duration = Duration::from_secs(60);
+const LIST_POLLING: Duration = Duration::from_secs(2);
+// How long the search engines of BTSearch may take
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(60);
+// Results fetched from BTSearch
+const SEARCH_LIMIT: usize = 50;
+
 //https://global.download.synology.com/download/Document/Software/DeveloperGuide/Package/DsControler/All/enu/Synology_Download_Station_Web_API.pdf
 
 pub struct DsControler<T> {
-    service: T,
+    pub(super) service: T,
+    pub(super) conf: SynologyConf,
+}
+
+// Named Download Station backends. A session is only opened
+// once a backend is actually used
+pub struct DsBackends<T> {
+    confs: BTreeMap<String, SynologyConf>,
+    default: String,
+    controlers: BTreeMap<String, OnceCell<DsControler<T>>>,
+}
+
+impl<T: HTTPService<Conf = SynologyConf>> DsBackends<T> {
+    pub fn new(confs: BTreeMap<String, SynologyConf>, default: String) -> Self {
+        let controlers = confs.keys().map(|k| (k.clone(), OnceCell::new())).collect();
+        Self {
+            confs,
+            default,
+            controlers,
+        }
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DsControler<T>> {
+        let conf = self.confs.get(name)?;
+        Some(self.controlers[name].get_or_init(|| DsControler::new(conf.clone())))
+    }
 }
 
 #[allow(dead_code)]
@@ -28,10 +77,13 @@ pub struct DsService {
     sid: String,
     api_information: ApiInformation,
     root_url: Url,
+    webapi_url: Url,
 }
 
 impl HTTPService for DsService {
-    fn new() -> Self {
+    type Conf = SynologyConf;
+
+    fn new(conf: &SynologyConf) -> Self {
         let client = Client::builder()
             .default_headers(
                 [(USER_AGENT, header::HeaderValue::from_static(API_USER_AGENT))]
@@ -44,8 +96,8 @@ impl HTTPService for DsService {
         // API Info
         let resp = client
             .get(format!(
-                "{}/webapi/query.cgi?api=SYNO.API.Info&version=1&method=query&query=SYNO.API.Auth,SYNO.DownloadStation.Task",
-                CONF.synology_root_api
+                "{}/webapi/query.cgi?api=SYNO.API.Info&version=1&method=query&query=all",
+                conf.root_api
             ))
             .send();
 
@@ -62,11 +114,11 @@ impl HTTPService for DsService {
                 let auth_resp = client
                     .get(format!(
                         "{}/webapi/{}?api=SYNO.API.Auth&version={}&method=login&account={}&passwd={}&session=DownloadStation&format=sid",
-                        CONF.synology_root_api,
+                        conf.root_api,
                         api_information.auth.path,
                         6,
-                        CONF.synology_user,
-                        CONF.synology_password
+                        conf.user,
+                        conf.password
                     ))
                     .send()
                     .unwrap();
@@ -80,17 +132,16 @@ impl HTTPService for DsService {
                     debug!("Login successful: {:?}", data);
 
                     let sid = String::from_str(data["data"]["sid"].as_str().unwrap()).unwrap();
-                    let url = format!(
-                        "{}/webapi/{}",
-                        CONF.synology_root_api, api_information.task.path,
-                    );
+                    let url = format!("{}/webapi/{}", conf.root_api, api_information.task.path,);
                     let root_url = Url::parse(url.as_str()).unwrap();
+                    let webapi_url = Url::parse(&format!("{}/webapi/", conf.root_api)).unwrap();
                     debug!("sid: {:?} root_url: {}", sid, root_url);
                     return Self {
                         client: client,
                         api_information: api_information,
                         sid,
                         root_url,
+                        webapi_url,
                     };
                 } else {
                     error!("Could not login to Synology API: {}", data.to_string());
@@ -118,7 +169,13 @@ impl HTTPService for DsService {
         // API root_url and the sid
         url.query_pairs_mut().append_pair("_sid", &self.sid);
 
-        let mut final_url = self.root_url.clone();
+        // requests go to the path of the API they target
+        let mut final_url = url
+            .query_pairs()
+            .find(|(k, _)| k == "api")
+            .and_then(|(_, api)| self.api_information.others.get(api.as_ref()))
+            .and_then(|api| self.webapi_url.join(&api.path).ok())
+            .unwrap_or(self.root_url.clone());
         final_url.set_query(url.query());
 
         let url_log = url.clone();
@@ -155,48 +212,235 @@ impl HTTPService for DsService {
     }
 }
 
-impl<T: HTTPService> DownloadingController for DsControler<T> {
-    fn new() -> Self
-    where
-        Self: Sized,
-        T: Sized,
-    {
-        let service = T::new();
-        return Self { service };
+impl<T: HTTPService<Conf = SynologyConf>> DsControler<T> {
+    pub fn new(conf: SynologyConf) -> Self {
+        let service = T::new(&conf);
+        Self { service, conf }
+    }
+
+    // Run a SYNO.DownloadStation.Task method acting on a
+    // single task id (delete, pause, resume)
+    fn task_action(&self, method: &str, id: &str, params: &[(&str, &str)]) -> bool {
+        let mut url = Url::parse(
+            format!(
+                "{}?api=SYNO.DownloadStation.Task&version=1&session=DownloadStation&method={}",
+                self.conf.root_api, method
+            )
+            .as_str(),
+        )
+        .unwrap();
+        url.query_pairs_mut().append_pair("id", id);
+        for (key, value) in params {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+
+        match self.service.send_request(url, Method::GET, None) {
+            Some(resp) => {
+                // DS answers success=true with a per-id error code
+                let failed = resp["data"]
+                    .as_array()
+                    .map(|arr| arr.iter().any(|r| r["error"].as_u64().unwrap_or(0) != 0))
+                    .unwrap_or(false);
+                if resp["success"] == true && !failed {
+                    true
+                } else {
+                    warn!("Could not {method} task {id}: {resp}");
+                    false
+                }
+            }
+            None => false,
+        }
+    }
+}
+
+// DownloadStation2 parameters are JSON values
+fn ds2_url(root_api: &str, api: &str, method: &str, params: &[(&str, Value)]) -> Url {
+    let mut url = Url::parse(&format!("{root_api}?api={api}&version=2&method={method}")).unwrap();
+    for (key, value) in params {
+        url.query_pairs_mut().append_pair(key, &value.to_string());
     }
+    url
+}
 
+impl<T: HTTPService<Conf = SynologyConf>> DsControler<T> {
+    fn info_request(&self, api: &str, method: &str) -> Option<Value> {
+        let url = Url::parse(&format!(
+            "{}?api={api}&version=1&method={method}",
+            self.conf.root_api
+        ))
+        .unwrap();
+        match self.service.send_request(url, Method::GET, None)? {
+            resp if resp["success"] == true => Some(resp["data"].clone()),
+            resp => {
+                warn!("Could not {method} {api}: {resp}");
+                None
+            }
+        }
+    }
+
+    // A method of a SYNO.DownloadStation API taking parameters
+    fn v1_request(
+        &self,
+        api: &str,
+        method: &str,
+        params: &[(&str, String)],
+    ) -> Result<Value, String> {
+        let mut url = Url::parse(&format!(
+            "{}?api={api}&version=1&method={method}",
+            self.conf.root_api
+        ))
+        .unwrap();
+        for (key, value) in params {
+            url.query_pairs_mut().append_pair(key, value);
+        }
+        self.ds2_request(url, Method::GET, None)
+    }
+
+    fn ds2_request(
+        &self,
+        url: Url,
+        method: Method,
+        payload: Option<Payload>,
+    ) -> Result<Value, String> {
+        match self.service.send_request(url, method, payload) {
+            Some(resp) if resp["success"] == true => Ok(resp["data"].clone()),
+            Some(resp) => Err(format!("Download Station error {}", resp["error"]["code"])),
+            None => Err(String::from("no response from Download Station")),
+        }
+    }
+
+    // Submit only some files of a torrent: DownloadStation2 first
+    // creates a list of the torrent's files, the task is created
+    // once some are picked
+    fn submit_selection(&self, task: &mut Task, selection: &FileSelection) -> Result<(), String> {
+        let destination = task.destination_folder.clone().unwrap_or_default();
+        let root_api = &self.conf.root_api;
+        let create = |params: &[(&str, Value)]| {
+            ds2_url(root_api, "SYNO.DownloadStation2.Task", "create", params)
+        };
+        let data = match &task.source {
+            Source::MAGNET(link) | Source::URL(link) => self.ds2_request(
+                create(&[
+                    ("type", json!("url")),
+                    ("url", json!([link])),
+                    ("destination", json!(destination)),
+                    ("create_list", json!(true)),
+                ]),
+                Method::GET,
+                None,
+            )?,
+            Source::FILE(file) => {
+                let part = Part::bytes(file.to_vec())
+                    .file_name("ds-companion.torrent")
+                    .mime_str("application/x-bittorrent")
+                    .unwrap();
+                let form = Form::new()
+                    .text("api", "SYNO.DownloadStation2.Task")
+                    .text("version", "2")
+                    .text("method", "create")
+                    .text("type", json!("file").to_string())
+                    .text("file", json!(["torrent"]).to_string())
+                    .text("destination", json!(destination).to_string())
+                    .text("create_list", "true")
+                    .part("torrent", part);
+                self.ds2_request(create(&[]), Method::POST, Some(Payload::FORM(form)))?
+            }
+        };
+        let list_id = data["list_id"][0]
+            .as_str()
+            .ok_or("Download Station did not list the files")?
+            .to_string();
+
+        // magnet links only get their files once the metadata are fetched
+        let mut waited = Duration::ZERO;
+        let files: Vec<String> = loop {
+            let list = self.ds2_request(
+                ds2_url(
+                    root_api,
+                    "SYNO.DownloadStation2.Task.List",
+                    "get",
+                    &[("list_id", json!(list_id))],
+                ),
+                Method::GET,
+                None,
+            )?;
+            let files: Vec<String> = list["files"]
+                .as_array()
+                .map(|files| {
+                    files
+                        .iter()
+                        .map(|f| f["name"].as_str().unwrap_or_default().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if !files.is_empty() || waited >= LIST_TIMEOUT {
+                break files;
+            }
+            thread::sleep(LIST_POLLING);
+            waited += LIST_POLLING;
+        };
+
+        let selected = selection.select(&files);
+        if selected.is_empty() {
+            return Err(format!("none of the {} files is selected", files.len()));
+        }
+        self.ds2_request(
+            ds2_url(
+                root_api,
+                "SYNO.DownloadStation2.Task.List.Polling",
+                "download",
+                &[
+                    ("list_id", json!(list_id)),
+                    ("selected", json!(selected)),
+                    ("destination", json!(destination)),
+                    ("create_subfolder", json!(true)),
+                ],
+            ),
+            Method::GET,
+            None,
+        )?;
+        task.selected_files = Some(selected.into_iter().map(|i| files[i].clone()).collect());
+        Ok(())
+    }
+}
+
+impl<T: HTTPService<Conf = SynologyConf>> DownloadingController for DsControler<T> {
     fn get_jobs_advancement(&self, tasks: &mut Vec<Task>) {
         let url = Url::parse(format!(
             "{}?api=SYNO.DownloadStation.Task&version=1&session=DownloadStation&method=list&additional=detail&username={}",
-            CONF.synology_root_api, CONF.synology_user
+            self.conf.root_api, self.conf.user
         ).as_str()).unwrap();
         let resp = self.service.send_request(url, Method::GET, None).unwrap();
 
         if resp["success"] == true {
             let distant_tasks: &Vec<Value> = resp["data"]["tasks"].as_array().unwrap();
+            let mut found = vec![false; tasks.len()];
             for obj in distant_tasks {
                 let uri = obj["additional"]["detail"]["uri"].as_str().unwrap();
                 let status = obj["status"].as_str().unwrap();
-                for task_idx in 0..tasks.len() {
-                    let task = &mut tasks[task_idx];
-                    match &task.source {
-                        Source::MAGNET(magnet_link) => {
-                            if magnet_link == uri
-                                && DS_TO_COMPANION_MAPPING[status] != task.get_status()
-                            {
-                                let s: TaskStatus = DS_TO_COMPANION_MAPPING[status];
-                                task.set_status(s);
-                                if s == TaskStatus::DONE || s == TaskStatus::FAILED {
-                                    tasks.remove(task_idx); // TODO: call deleter from set_status instead?
-                                }
-                            } else {
-                                debug!("Nothing new for task: {}", magnet_link);
-                            }
-                        }
-                        Source::FILE(_) => {
-                            panic!("Not implemented")
-                        }
+                let title = obj["title"].as_str();
+                for (task, found) in tasks.iter_mut().zip(found.iter_mut()) {
+                    let matching = task.source.matches(Some(uri), title);
+                    if matching {
+                        *found = true;
+                        task.size = obj["size"].as_u64().or(task.size);
                     }
+                    if matching && DS_TO_COMPANION_MAPPING[status] != task.get_status() {
+                        task.set_status(DS_TO_COMPANION_MAPPING[status]);
+                    } else {
+                        debug!("Nothing new for task: {}", task.message_id);
+                    }
+                }
+            }
+            // removed from DSM or with the buttons of its status, whether
+            // it had started or not
+            for (task, found) in tasks.iter_mut().zip(found) {
+                if !found && task.is_active() {
+                    let name = task.source.name().unwrap_or(task.message_id.clone());
+                    task.set_status_and_notify(
+                        TaskStatus::FAILED,
+                        &format!("CANCELLED: {name} is no longer in Download Station"),
+                    );
                 }
             }
         } else {
@@ -204,11 +448,92 @@ impl<T: HTTPService> DownloadingController for DsControler<T> {
         }
     }
 
+    fn list_tasks(&self) -> Option<Vec<RemoteTask>> {
+        let url = Url::parse(format!(
+            "{}?api=SYNO.DownloadStation.Task&version=1&session=DownloadStation&method=list&additional=detail,transfer",
+            self.conf.root_api
+        ).as_str()).unwrap();
+        let resp = self.service.send_request(url, Method::GET, None)?;
+
+        if resp["success"] == true {
+            match serde_json::from_value::<Vec<DsTaskObject>>(resp["data"]["tasks"].clone()) {
+                Ok(objs) => Some(objs.into_iter().map(RemoteTask::from).collect()),
+                Err(e) => {
+                    error!("Could not parse task list: {e}");
+                    None
+                }
+            }
+        } else {
+            error!("Could not list tasks: {resp}");
+            None
+        }
+    }
+
+    fn get_task(&self, id: &str) -> Option<RemoteTask> {
+        let mut url = Url::parse(format!(
+            "{}?api=SYNO.DownloadStation.Task&version=1&session=DownloadStation&method=getinfo&additional=detail,transfer",
+            self.conf.root_api
+        ).as_str()).unwrap();
+        url.query_pairs_mut().append_pair("id", id);
+        let resp = self.service.send_request(url, Method::GET, None)?;
+
+        if resp["success"] == true {
+            let obj = resp["data"]["tasks"].get(0)?.clone();
+            match serde_json::from_value::<DsTaskObject>(obj) {
+                Ok(obj) => Some(RemoteTask::from(obj)),
+                Err(e) => {
+                    error!("Could not parse task {id}: {e}");
+                    None
+                }
+            }
+        } else {
+            warn!("Could not get task {id}: {resp}");
+            None
+        }
+    }
+
+    fn delete_task(&self, id: &str) -> bool {
+        // what an unfinished task downloaded is not moved to its destination
+        self.task_action("delete", id, &[("force_complete", "false")])
+    }
+
+    fn pause_task(&self, id: &str) -> bool {
+        self.task_action("pause", id, &[])
+    }
+
+    fn resume_task(&self, id: &str) -> bool {
+        self.task_action("resume", id, &[])
+    }
+
+    fn statistics(&self) -> Option<Statistics> {
+        let speeds = self.info_request("SYNO.DownloadStation.Statistic", "getinfo")?;
+        let info = self.info_request("SYNO.DownloadStation.Info", "getinfo")?;
+        let config = self.info_request("SYNO.DownloadStation.Info", "getconfig")?;
+        Some(Statistics {
+            version: info["version_string"].as_str().map(String::from),
+            download_speed: speeds["speed_download"].as_u64().unwrap_or(0),
+            upload_speed: speeds["speed_upload"].as_u64().unwrap_or(0),
+            default_destination: config["default_destination"].as_str().map(String::from),
+        })
+    }
+
     fn submit_task(&self, task: &mut Task) {
+        if let Some(selection) = task.request.files.clone() {
+            return match self.submit_selection(task, &selection) {
+                Ok(()) => task.set_status(TaskStatus::SUBMITTED),
+                Err(e) => {
+                    error!("Could not submit download task {}: {e}", task.message_id);
+                    task.set_status_and_notify(
+                        TaskStatus::FAILED,
+                        &format!("FAILED: {e} <@{}>", task.user_id),
+                    );
+                }
+            };
+        }
         let mut url = Url::parse(
             format!(
                 "{}?api=SYNO.DownloadStation.Task&version=1&session=DownloadStation&method=create",
-                CONF.synology_root_api
+                self.conf.root_api
             )
             .as_str(),
         )
@@ -221,24 +546,26 @@ impl<T: HTTPService> DownloadingController for DsControler<T> {
         }
 
         let resp = match &task.source {
-            Source::MAGNET(magnet_link) => {
-                url.query_pairs_mut().append_pair("uri", &magnet_link);
-
-                let resp = self.service.send_request(url, Method::GET, None);
-                resp
-            }
-            Source::FILE(_) => {
-                // TODO: implement when missing documentation is fixed
-                //let part = Part::bytes(file.to_vec())
-                //    .file_name("bio.torrent") // TODO: make variable
-                //    .mime_str("application/x-bittorrent")
-                //    .unwrap();
-                //let form = reqwest::blocking::multipart::Form::new().part("file", part);
-                //let resp = self
-                //    .service
-                //    .send_request(url, Method::POST, Some(Payload::FORM(form)));
-                //resp
-                panic!("Not implemented")
+            Source::MAGNET(link) | Source::URL(link) => {
+                url.query_pairs_mut().append_pair("uri", link);
+
+                self.service.send_request(url, Method::GET, None)
+            }
+            Source::FILE(file) => {
+                let part = Part::bytes(file.to_vec())
+                    .file_name("ds-companion.torrent")
+                    .mime_str("application/x-bittorrent")
+                    .unwrap();
+                let mut form = Form::new()
+                    .text("api", "SYNO.DownloadStation.Task")
+                    .text("version", "1")
+                    .text("method", "create");
+                if let Some(destination) = &task.destination_folder {
+                    form = form.text("destination", destination.clone());
+                }
+                let form = form.part("file", part);
+                self.service
+                    .send_request(url, Method::POST, Some(Payload::FORM(form)))
             }
         };
         match resp {
@@ -267,6 +594,230 @@ impl<T: HTTPService> DownloadingController for DsControler<T> {
     }
 }
 
+impl<T: HTTPService<Conf = SynologyConf>> SpeedController for DsControler<T> {
+    fn speed_limits(&self) -> Option<SpeedLimits> {
+        let config = self.info_request("SYNO.DownloadStation.Info", "getconfig")?;
+        let limit = |key: &str| config[key].as_u64().unwrap_or(0);
+        Some(SpeedLimits {
+            bt_download: limit("bt_max_download"),
+            bt_upload: limit("bt_max_upload"),
+            http_download: limit("http_max_download"),
+            ftp_download: limit("ftp_max_download"),
+        })
+    }
+
+    fn set_speed_limits(&self, limits: &SpeedLimits) -> Result<(), String> {
+        self.v1_request(
+            "SYNO.DownloadStation.Info",
+            "setconfig",
+            &[
+                ("bt_max_download", limits.bt_download.to_string()),
+                ("bt_max_upload", limits.bt_upload.to_string()),
+                ("http_max_download", limits.http_download.to_string()),
+                ("ftp_max_download", limits.ftp_download.to_string()),
+            ],
+        )
+        .map(|_| ())
+    }
+
+    fn schedule_enabled(&self) -> Option<bool> {
+        let config = self.info_request("SYNO.DownloadStation.Schedule", "getconfig")?;
+        config["enabled"].as_bool()
+    }
+
+    fn set_schedule(&self, enabled: bool) -> Result<(), String> {
+        self.v1_request(
+            "SYNO.DownloadStation.Schedule",
+            "setconfig",
+            &[("enabled", enabled.to_string())],
+        )
+        .map(|_| ())
+    }
+}
+
+// Ids are numbers in some DSM versions, strings in others
+fn id_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+// The v1 API only lists and refreshes RSS sites, they are added and
+// deleted through DownloadStation2 as DSM does
+impl<T: HTTPService<Conf = SynologyConf>> RssController for DsControler<T> {
+    fn list_rss_sites(&self) -> Option<Vec<RssSite>> {
+        let data = self.info_request("SYNO.DownloadStation.RSS.Site", "list")?;
+        let sites = data["sites"].as_array()?;
+        Some(
+            sites
+                .iter()
+                .map(|site| RssSite {
+                    id: id_string(&site["id"]),
+                    title: site["title"].as_str().unwrap_or_default().to_string(),
+                    url: site["url"].as_str().unwrap_or_default().to_string(),
+                    updated_at: site["last_update"].as_i64(),
+                })
+                .collect(),
+        )
+    }
+
+    fn add_rss_site(&self, url: &str) -> Result<(), String> {
+        let url = ds2_url(
+            &self.conf.root_api,
+            "SYNO.DownloadStation2.RSS.Site",
+            "create",
+            &[("url", json!(url))],
+        );
+        self.ds2_request(url, Method::GET, None).map(|_| ())
+    }
+
+    fn refresh_rss_site(&self, id: Option<&str>) -> Result<(), String> {
+        let ids = match id {
+            Some(id) => id.to_string(),
+            None => self
+                .list_rss_sites()
+                .ok_or("could not list the RSS sites")?
+                .iter()
+                .map(|site| site.id.clone())
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        self.v1_request("SYNO.DownloadStation.RSS.Site", "refresh", &[("id", ids)])
+            .map(|_| ())
+    }
+
+    fn delete_rss_site(&self, id: &str) -> Result<(), String> {
+        let url = ds2_url(
+            &self.conf.root_api,
+            "SYNO.DownloadStation2.RSS.Site",
+            "delete",
+            &[("id", json!([id]))],
+        );
+        self.ds2_request(url, Method::GET, None).map(|_| ())
+    }
+
+    fn list_rss_items(&self, id: &str) -> Option<Vec<RssItem>> {
+        let data = self
+            .v1_request(
+                "SYNO.DownloadStation.RSS.Feed",
+                "list",
+                &[("id", id.to_string())],
+            )
+            .map_err(|e| warn!("Could not list the items of RSS site {id}: {e}"))
+            .ok()?;
+        let feeds = data["feeds"].as_array()?;
+        Some(
+            feeds
+                .iter()
+                .map(|feed| RssItem {
+                    title: feed["title"].as_str().unwrap_or_default().to_string(),
+                    size: feed["size"]
+                        .as_u64()
+                        .or(feed["size"].as_str().and_then(|s| s.parse().ok())),
+                    published: feed["time"].as_i64(),
+                })
+                .collect(),
+        )
+    }
+}
+
+// Numbers are strings in some DSM versions
+fn number(value: &Value) -> u64 {
+    value
+        .as_u64()
+        .or(value.as_str().and_then(|s| s.parse().ok()))
+        .unwrap_or(0)
+}
+
+impl<T: HTTPService<Conf = SynologyConf>> DsControler<T> {
+    // Id of a BTSearch category from its name
+    fn search_category(&self, name: &str) -> Result<String, String> {
+        let data = self.v1_request("SYNO.DownloadStation.BTSearch", "getCategory", &[])?;
+        let categories = data["categories"].as_array().cloned().unwrap_or_default();
+        categories
+            .iter()
+            .find(|c| {
+                c["title"]
+                    .as_str()
+                    .is_some_and(|t| t.eq_ignore_ascii_case(name))
+            })
+            .map(|c| id_string(&c["id"]))
+            .ok_or(format!(
+                "unknown category {name}, expects one of: {}",
+                categories
+                    .iter()
+                    .filter_map(|c| c["title"].as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+    }
+
+    // Poll the results until every search engine is done
+    fn search_results(&self, taskid: &str, category: Option<&str>) -> Result<Value, String> {
+        let mut params = vec![
+            ("taskid", taskid.to_string()),
+            ("offset", String::from("0")),
+            ("limit", SEARCH_LIMIT.to_string()),
+            ("sort_by", String::from("seeds")),
+            ("sort_direction", String::from("desc")),
+        ];
+        if let Some(category) = category {
+            params.push(("filter_category", category.to_string()));
+        }
+        let mut waited = Duration::ZERO;
+        loop {
+            let data = self.v1_request("SYNO.DownloadStation.BTSearch", "list", &params)?;
+            if data["finished"] == true || waited >= SEARCH_TIMEOUT {
+                return Ok(data);
+            }
+            thread::sleep(LIST_POLLING);
+            waited += LIST_POLLING;
+        }
+    }
+}
+
+impl<T: HTTPService<Conf = SynologyConf>> SearchController for DsControler<T> {
+    fn search(&self, terms: &str, category: Option<&str>) -> Result<Vec<SearchResult>, String> {
+        let category = category.map(|c| self.search_category(c)).transpose()?;
+        let data = self.v1_request(
+            "SYNO.DownloadStation.BTSearch",
+            "start",
+            &[
+                ("keyword", terms.to_string()),
+                ("module", String::from("enabled")),
+            ],
+        )?;
+        let taskid = data["taskid"].as_str().ok_or("no search task")?.to_string();
+        let results = self.search_results(&taskid, category.as_deref());
+        // the search is cleaned even when it failed
+        if let Err(e) = self.v1_request(
+            "SYNO.DownloadStation.BTSearch",
+            "clean",
+            &[("taskid", taskid.clone())],
+        ) {
+            warn!("Could not clean search {taskid}: {e}");
+        }
+        let items = results?["items"].as_array().cloned().unwrap_or_default();
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                Some(SearchResult {
+                    title: item["title"].as_str()?.to_string(),
+                    uri: item["download_uri"]
+                        .as_str()
+                        .filter(|uri| !uri.is_empty())?
+                        .to_string(),
+                    size: number(&item["size"]),
+                    seeds: number(&item["seeds"]),
+                    leechs: number(&item["leechs"]),
+                    provider: item["provider"].as_str().unwrap_or_default().to_string(),
+                })
+            })
+            .collect())
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
 
@@ -277,8 +828,15 @@ pub mod tests {
     use serde_json::{json, Value};
 
     use crate::{
+        conf::{DiscordConf, SynologyConf},
+        core::request::Request,
+        core::speed::SpeedLimits,
         core::task::{Source, Task, TaskStatus},
-        core::traits::{DownloadingController, HTTPService, MessagingController, Payload},
+        core::torrent::tests::torrent_file,
+        core::traits::{
+            DownloadingController, HTTPService, Payload, RssController, SearchController,
+            SpeedController,
+        },
         services::{
             discord::DiscordController,
             download_station::{DsControler, DS_TO_COMPANION_MAPPING},
@@ -287,7 +845,9 @@ pub mod tests {
 
     struct DiscordServiceMock {}
     impl HTTPService for DiscordServiceMock {
-        fn new() -> Self {
+        type Conf = DiscordConf;
+
+        fn new(_: &DiscordConf) -> Self {
             Self {}
         }
         fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
@@ -304,7 +864,9 @@ pub mod tests {
     }
 
     impl HTTPService for DsServiceMock {
-        fn new() -> Self {
+        type Conf = SynologyConf;
+
+        fn new(_: &SynologyConf) -> Self {
             let payload: RefCell<Option<Payload>> =
                 RefCell::new(Some(Payload::BODY(Body::from(vec![])))); // inject here
             let url = RefCell::new(Url::parse("http://somewhere").unwrap());
@@ -325,6 +887,14 @@ pub mod tests {
         }
     }
 
+    fn conf() -> SynologyConf {
+        SynologyConf {
+            root_api: "http://127.0.0.1:5000".to_string(),
+            user: "user".to_string(),
+            password: "password".to_string(),
+        }
+    }
+
     #[test]
     fn status_mapping() {
         let s = String::from_str("downloading").unwrap();
@@ -334,34 +904,38 @@ pub mod tests {
         assert!(DS_TO_COMPANION_MAPPING[t.as_str()] == TaskStatus::SUBMITTED);
     }
 
-    //
-    //#[test]
-    //fn file_handling() {
-    //    let controler = DsControler::<DsServiceMock>::new();
-    //    let messaging_controler = DiscordController::<DiscordServiceMock>::new();
-    //    let file = Bytes::from("SOME_FILE");
-    //    let mut task = Task::new(
-    //        Source::FILE(file),
-    //        String::from_str("1").unwrap(),
-    //        &messaging_controler,
-    //        Some(String::from_str("videos/Movies").unwrap()),
-    //        String::from_str("1").unwrap(),
-    //    );
-    //    controler.submit_task(&mut task);
-    //    let receive_req = controler.service.payload.into_inner().unwrap();
-    //    // HERE: test
-    //}
+    #[test]
+    fn file_handling() {
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let messaging_controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf::default());
+        let file = Bytes::from("SOME_FILE");
+        let mut task = Task::new(
+            Source::FILE(file),
+            String::from_str("1").unwrap(),
+            String::from_str("1").unwrap(),
+            &messaging_controler,
+            Some(String::from_str("videos/Movies").unwrap()),
+            String::from_str("1").unwrap(),
+        );
+        controler.submit_task(&mut task);
+        let receive_req = controler.service.payload.into_inner();
+        assert!(matches!(receive_req, Some(Payload::FORM(_))));
+        assert!(task.get_status() == TaskStatus::SUBMITTED);
+    }
 
     #[test]
     fn destination_folder_set_in_url() {
-        let controler = DsControler::<DsServiceMock>::new();
-        let messaging_controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let messaging_controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf::default());
         let mut task = Task::new(
             Source::MAGNET(
                 String::from_str("magnet:?xt=urn:btih:A3057BB12D25F9F391806D819A9420FA29A86712&")
                     .unwrap(),
             ),
             String::from_str("1").unwrap(),
+            String::from_str("1").unwrap(),
             &messaging_controler,
             Some(String::from_str("videos/Movies").unwrap()),
             String::from_str("1").unwrap(),
@@ -375,6 +949,17 @@ pub mod tests {
         ))
     }
 
+    #[test]
+    fn task_actions() {
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        controler.delete_task("dbid_1");
+        let url = controler.service.url.into_inner();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&(String::from("method"), String::from("delete"))));
+        assert!(query.contains(&(String::from("force_complete"), String::from("false"))));
+        assert!(query.contains(&(String::from("id"), String::from("dbid_1"))));
+    }
+
     #[test]
     fn advancement_updating() {
         struct DsServiceMock {
@@ -382,7 +967,9 @@ pub mod tests {
         }
 
         impl HTTPService for DsServiceMock {
-            fn new() -> Self {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
                 let time_called: Mutex<i8> = Mutex::new(0);
                 Self { time_called }
             }
@@ -419,11 +1006,13 @@ pub mod tests {
                 panic!("Not implemented")
             }
         }
-        let controler = DsControler::<DsServiceMock>::new();
-        let messaging_controler = DiscordController::<DiscordServiceMock>::new();
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let messaging_controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf::default());
         let task = Task::new(
             Source::MAGNET(String::from_str("magnet:?xt9420FA29A").unwrap()),
             String::from_str("1").unwrap(),
+            String::from_str("1").unwrap(),
             &messaging_controler,
             Some(String::from_str("videos/Movies").unwrap()),
             String::from_str("1").unwrap(),
@@ -438,5 +1027,306 @@ pub mod tests {
         matches!(tasks[0].get_status(), TaskStatus::DONE);
     }
 
-    // TODO: ERROR TESTING
+    #[test]
+    fn torrent_advancement() {
+        struct DsServiceMock {}
+
+        impl HTTPService for DsServiceMock {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
+                Self {}
+            }
+            fn send_request(&self, _: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                Some(json!({"success": true, "data":{"tasks":[
+                    {"title": "other", "size": 10, "status":"downloading", "additional":{"detail":{"uri":"other.torrent"}}},
+                    {"title": "debian", "size": 1536, "status":"finished", "additional":{"detail":{"uri":"ds-companion.torrent"}}}
+                ]}}))
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+        }
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let messaging_controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf::default());
+        let task = Task::new(
+            Source::FILE(Bytes::from(torrent_file())),
+            String::from("1"),
+            String::from("1"),
+            &messaging_controler,
+            None,
+            String::from("1"),
+        );
+        let mut tasks = vec![task];
+        controler.get_jobs_advancement(&mut tasks);
+        assert!(tasks[0].get_status() == TaskStatus::DONE);
+        assert!(tasks[0].size == Some(1536));
+
+        // tasks removed are over, whether they had started or not
+        for status in [TaskStatus::SUBMITTED, TaskStatus::DOWNLOADING] {
+            let mut task = Task::new(
+                Source::URL(String::from("https://example.org/removed.iso")),
+                String::from("2"),
+                String::from("1"),
+                &messaging_controler,
+                None,
+                String::from("1"),
+            );
+            task.set_status(status);
+            let mut tasks = vec![task];
+            controler.get_jobs_advancement(&mut tasks);
+            assert!(tasks[0].get_status() == TaskStatus::FAILED);
+        }
+    }
+
+    #[test]
+    fn file_selection() {
+        struct DsServiceMock {
+            urls: RefCell<Vec<Url>>,
+        }
+
+        impl HTTPService for DsServiceMock {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
+                Self {
+                    urls: RefCell::new(vec![]),
+                }
+            }
+            fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                let method = url
+                    .query_pairs()
+                    .find(|(key, _)| key == "method")
+                    .unwrap()
+                    .1
+                    .to_string();
+                self.urls.borrow_mut().push(url);
+                match method.as_str() {
+                    "create" => Some(json!({"success": true, "data": {"list_id": ["list1"]}})),
+                    "get" => Some(json!({"success": true, "data": {"files": [
+                        {"index": 0, "name": "Show/S01E01.mkv", "size": 1000},
+                        {"index": 1, "name": "Show/S01E01.fr.srt", "size": 10},
+                        {"index": 2, "name": "Show/S01E02.mkv", "size": 1000}
+                    ]}})),
+                    "download" => Some(json!({"success": true, "data": {}})),
+                    _ => None,
+                }
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+        }
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let messaging_controler =
+            DiscordController::<DiscordServiceMock>::new(DiscordConf::default());
+        let mut task = Task::new(
+            Source::MAGNET(format!("magnet:?xt=urn:btih:{}", "a".repeat(40))),
+            String::from("1"),
+            String::from("1"),
+            &messaging_controler,
+            Some(String::from("videos/Shows")),
+            String::from("1"),
+        );
+        task.request = Request::parse("Files: 3, *.srt").unwrap();
+        controler.submit_task(&mut task);
+        assert!(task.get_status() == TaskStatus::SUBMITTED);
+        assert!(
+            task.selected_files.as_deref()
+                == Some(
+                    &[
+                        String::from("Show/S01E01.fr.srt"),
+                        String::from("Show/S01E02.mkv")
+                    ][..]
+                )
+        );
+        let urls = controler.service.urls.into_inner();
+        assert!(urls.len() == 3);
+        assert!(urls[0].as_str().contains("api=SYNO.DownloadStation2.Task&"));
+        assert!(urls[1].as_str().contains("list_id=%22list1%22"));
+        assert!(urls[2].as_str().contains("selected=%5B1%2C2%5D"));
+        assert!(urls[2]
+            .as_str()
+            .contains("destination=%22videos%2FShows%22"));
+
+        // nothing matches: nothing gets downloaded
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        task.request = Request::parse("Files: *.iso").unwrap();
+        task.selected_files = None;
+        controler.submit_task(&mut task);
+        assert!(task.get_status() == TaskStatus::FAILED);
+        assert!(controler.service.urls.into_inner().len() == 2);
+    }
+
+    #[test]
+    fn speed_settings() {
+        struct DsServiceMock {
+            urls: RefCell<Vec<Url>>,
+        }
+
+        impl HTTPService for DsServiceMock {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
+                Self {
+                    urls: RefCell::new(vec![]),
+                }
+            }
+            fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                let resp = match (url.as_str().contains("Schedule"), url.as_str()) {
+                    (_, url) if url.contains("method=setconfig") => json!({"success": true}),
+                    (true, _) => json!({"success": true, "data": {"enabled": true}}),
+                    (false, _) => json!({"success": true, "data": {
+                        "bt_max_download": 2048, "bt_max_upload": 100,
+                        "http_max_download": 0, "default_destination": "downloads"
+                    }}),
+                };
+                self.urls.borrow_mut().push(url);
+                Some(resp)
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+        }
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let limits = controler.speed_limits().unwrap();
+        assert!(limits.bt_download == 2048 && limits.bt_upload == 100);
+        assert!(limits.http_download == 0 && limits.ftp_download == 0);
+        assert!(controler.schedule_enabled() == Some(true));
+
+        controler
+            .set_speed_limits(&SpeedLimits::default().with_download(512))
+            .unwrap();
+        controler.set_schedule(false).unwrap();
+        let urls = controler.service.urls.into_inner();
+        assert!(urls[2]
+            .as_str()
+            .contains("method=setconfig&bt_max_download=512&bt_max_upload=0"));
+        assert!(urls[2].as_str().contains("ftp_max_download=512"));
+        assert!(urls[3].as_str().contains(
+            "api=SYNO.DownloadStation.Schedule&version=1&method=setconfig&enabled=false"
+        ));
+    }
+
+    #[test]
+    fn rss_sites() {
+        struct DsServiceMock {
+            urls: RefCell<Vec<Url>>,
+        }
+
+        impl HTTPService for DsServiceMock {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
+                Self {
+                    urls: RefCell::new(vec![]),
+                }
+            }
+            fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                let resp = match url.as_str() {
+                    u if u.contains("RSS.Site&version=1&method=list") => {
+                        json!({"success": true, "data": {"sites": [
+                            {"id": 3, "title": "Linux", "url": "https://example.org/rss", "last_update": 1700000000},
+                            {"id": "7", "title": "BSD", "url": "https://example.org/bsd"}
+                        ]}})
+                    }
+                    u if u.contains("RSS.Feed") => {
+                        json!({"success": true, "data": {"feeds": [
+                            {"title": "debian.iso", "size": "1024", "time": 1700000000}
+                        ]}})
+                    }
+                    u if u.contains("method=delete") => {
+                        json!({"success": false, "error": {"code": 404}})
+                    }
+                    _ => json!({"success": true, "data": {}}),
+                };
+                self.urls.borrow_mut().push(url);
+                Some(resp)
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+        }
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let sites = controler.list_rss_sites().unwrap();
+        assert!(sites.len() == 2 && sites[0].id == "3" && sites[1].id == "7");
+        assert!(
+            sites[0].to_string()
+                == "#3 Linux (https://example.org/rss), updated 2023-11-14 22:13 UTC"
+        );
+        let items = controler.list_rss_items("3").unwrap();
+        assert!(items[0].size == Some(1024));
+
+        controler.add_rss_site("https://example.org/new").unwrap();
+        controler.refresh_rss_site(None).unwrap();
+        assert!(controler.delete_rss_site("3").unwrap_err() == "Download Station error 404");
+        let urls = controler.service.urls.into_inner();
+        assert!(urls[2]
+            .as_str()
+            .contains("SYNO.DownloadStation2.RSS.Site&version=2&method=create&url=%22https"));
+        assert!(urls[4].as_str().ends_with("method=refresh&id=3%2C7"));
+        assert!(urls[5].as_str().contains("method=delete&id=%5B%223%22%5D"));
+    }
+
+    #[test]
+    fn bt_search() {
+        struct DsServiceMock {
+            urls: RefCell<Vec<Url>>,
+        }
+
+        impl HTTPService for DsServiceMock {
+            type Conf = SynologyConf;
+
+            fn new(_: &SynologyConf) -> Self {
+                Self {
+                    urls: RefCell::new(vec![]),
+                }
+            }
+            fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+                let method = url
+                    .query_pairs()
+                    .find(|(key, _)| key == "method")
+                    .unwrap()
+                    .1
+                    .to_string();
+                self.urls.borrow_mut().push(url);
+                let data = match method.as_str() {
+                    "getCategory" => json!({"categories": [
+                        {"id": "_allcat_", "title": "All"},
+                        {"id": "os", "title": "Software"}
+                    ]}),
+                    "start" => json!({"taskid": "search1"}),
+                    "list" => json!({"finished": true, "items": [
+                        {"title": "debian.iso", "download_uri": "https://example.org/debian.torrent",
+                         "size": "3758096384", "seeds": 120, "leechs": 3, "provider": "linuxtracker"},
+                        {"title": "no link", "download_uri": "", "seeds": 1}
+                    ]}),
+                    _ => json!({}),
+                };
+                Some(json!({"success": true, "data": data}))
+            }
+            fn download_file(&self, _: Url) -> Option<Bytes> {
+                panic!("Not implemented")
+            }
+        }
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let results = controler.search("debian", Some("software")).unwrap();
+        assert!(results.len() == 1);
+        assert!(results[0].size == 3758096384 && results[0].seeds == 120);
+        assert!(results[0].uri == "https://example.org/debian.torrent");
+        let urls = controler.service.urls.into_inner();
+        assert!(urls.len() == 4);
+        assert!(urls[1]
+            .as_str()
+            .contains("method=start&keyword=debian&module=enabled"));
+        assert!(urls[2].as_str().contains("sort_by=seeds"));
+        assert!(urls[2].as_str().ends_with("filter_category=os"));
+        assert!(urls[3].as_str().ends_with("method=clean&taskid=search1"));
+
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        assert!(
+            controler.search("debian", Some("music")).unwrap_err()
+                == "unknown category music, expects one of: All, Software"
+        );
+    }
 }
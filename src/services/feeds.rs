@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+
+use crate::conf::RssConf;
+use crate::core::rss::{parse_feed, FeedFilter, FeedItem, Seen};
+
+use super::API_USER_AGENT;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Polls the feeds of the configuration for new items worth downloading
+pub struct FeedWatcher {
+    feeds: Vec<(RssConf, FeedFilter)>,
+    client: Client,
+    seen: Seen,
+}
+
+impl FeedWatcher {
+    // Filters are checked with the configuration, feeds with invalid
+    // ones are ignored
+    pub fn new(feeds: Vec<RssConf>, seen: Seen) -> Self {
+        let feeds = feeds
+            .into_iter()
+            .filter_map(
+                |feed| match FeedFilter::new(&feed.filters, feed.exclude.as_deref()) {
+                    Ok(filter) => Some((feed, filter)),
+                    Err(e) => {
+                        error!("Ignoring feed {}: {e}", feed.url);
+                        None
+                    }
+                },
+            )
+            .collect();
+        let client = Client::builder().timeout(HTTP_TIMEOUT).build().unwrap();
+        Self {
+            feeds,
+            client,
+            seen,
+        }
+    }
+
+    // New matching items of every feed, with the feed they come from
+    pub fn poll(&mut self) -> Vec<(RssConf, FeedItem)> {
+        let mut found = vec![];
+        for (feed, filter) in &self.feeds {
+            let items = match self.fetch(&feed.url) {
+                Ok(items) => items,
+                Err(e) => {
+                    warn!("Could not read feed {}: {e}", feed.url);
+                    continue;
+                }
+            };
+            for item in self.seen.unseen(&feed.url, items) {
+                if filter.matches(&item.title) {
+                    info!("New item in feed {}: {}", feed.url, item.title);
+                    found.push((feed.clone(), item));
+                } else {
+                    debug!("Skipping {} of feed {}", item.title, feed.url);
+                }
+            }
+        }
+        self.seen.save();
+        found
+    }
+
+    fn fetch(&self, url: &str) -> Result<Vec<FeedItem>, String> {
+        let resp = self
+            .client
+            .get(url)
+            .header(USER_AGENT, API_USER_AGENT)
+            .send()
+            .map_err(|e| format!("unreachable ({e})"))?;
+        if !resp.status().is_success() {
+            return Err(format!("HTTP {}", resp.status().as_u16()));
+        }
+        parse_feed(&resp.text().map_err(|e| e.to_string())?)
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::thread;
+
+    use tiny_http::{Response, Server};
+
+    use crate::{conf::RssConf, core::rss::Seen, services::feeds::FeedWatcher};
+
+    fn feed(titles: &[&str]) -> String {
+        let items: String = titles
+            .iter()
+            .map(|t| {
+                format!(
+                    "<item><title>{t}</title><link>https://example.org/{t}.torrent</link></item>"
+                )
+            })
+            .collect();
+        format!("<rss version=\"2.0\"><channel><title>Linux</title>{items}</channel></rss>")
+    }
+
+    #[test]
+    fn feed_watching() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        let stub = thread::spawn(move || {
+            let bodies = [
+                feed(&["debian-12.4"]),
+                feed(&["debian-12.5", "fedora-40", "debian-12.4"]),
+                String::from("not a feed"),
+            ];
+            for body in bodies {
+                let request = server.recv().unwrap();
+                request.respond(Response::from_string(body)).unwrap();
+            }
+        });
+
+        let mut watcher = FeedWatcher::new(
+            vec![RssConf {
+                url: format!("http://{address}/rss"),
+                filters: vec![String::from("^debian")],
+                channel: String::from("1"),
+                user: String::from("2"),
+                ..Default::default()
+            }],
+            Seen::default(),
+        );
+        // the items already there when the feed is first read are skipped
+        assert!(watcher.poll().is_empty());
+        let found = watcher.poll();
+        assert!(found.len() == 1);
+        assert!(found[0].1.link == "https://example.org/debian-12.5.torrent");
+        assert!(found[0].0.user == "2");
+        assert!(watcher.poll().is_empty());
+        stub.join().unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::{Method, Url};
+use serde_json::Value;
+
+use crate::conf::SynologyConf;
+use crate::core::destination::Folder;
+use crate::core::organize::Entry;
+use crate::core::stats::Space;
+use crate::core::traits::{HTTPService, StorageController};
+
+use super::download_station::DsControler;
+
+// How often background operations (move, extraction...) are checked,
+// and how long they may take
+const JOB_POLLING: Duration = Duration::from_secs(2);
+const JOB_TIMEOUT: Duration = Duration::from_secs(3600);
+
+impl<T: HTTPService<Conf = SynologyConf>> DsControler<T> {
+    fn file_station(
+        &self,
+        api: &str,
+        version: u8,
+        method: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Value, String> {
+        let mut url = Url::parse(&format!(
+            "{}?api=SYNO.FileStation.{api}&version={version}&method={method}",
+            self.conf.root_api
+        ))
+        .unwrap();
+        url.query_pairs_mut().extend_pairs(params);
+        match self.service.send_request(url, Method::GET, None) {
+            Some(resp) if resp["success"] == true => Ok(resp["data"].clone()),
+            Some(resp) => Err(format!("FileStation {api} error {}", resp["error"]["code"])),
+            None => Err(String::from("no response from FileStation")),
+        }
+    }
+
+    // Start a background operation and wait for it to be over. Stopped
+    // when it takes too long
+    fn background(&self, api: &str, version: u8, params: &[(&str, &str)]) -> Result<(), String> {
+        let started = self.file_station(api, version, "start", params)?;
+        let id = started["taskid"]
+            .as_str()
+            .ok_or(format!("FileStation {api} did not start"))?
+            .to_string();
+        let mut waited = Duration::ZERO;
+        loop {
+            let status = self.file_station(api, version, "status", &[("taskid", &id)])?;
+            if !status["error"].is_null() {
+                return Err(format!("FileStation {api} failed: {}", status["error"]));
+            }
+            if status["finished"] == true {
+                return Ok(());
+            }
+            if waited >= JOB_TIMEOUT {
+                if let Err(e) = self.file_station(api, version, "stop", &[("taskid", &id)]) {
+                    warn!("Could not stop FileStation {api} {id}: {e}");
+                }
+                return Err(format!(
+                    "FileStation {api} not over after {} minutes",
+                    JOB_TIMEOUT.as_secs() / 60
+                ));
+            }
+            info!(
+                "FileStation {api} {id}: {:.0}%",
+                status["progress"].as_f64().unwrap_or(0.0) * 100.0
+            );
+            thread::sleep(JOB_POLLING);
+            waited += JOB_POLLING;
+        }
+    }
+}
+
+// FileStation shares the Download Station session
+impl<T: HTTPService<Conf = SynologyConf>> StorageController for DsControler<T> {
+    fn list_folders(&self, path: &str) -> Option<Vec<Folder>> {
+        let mut url = Url::parse(&format!(
+            "{}?api=SYNO.FileStation.List&version=2&additional=perm",
+            self.conf.root_api
+        ))
+        .unwrap();
+        if path.is_empty() {
+            url.query_pairs_mut().append_pair("method", "list_share");
+        } else {
+            url.query_pairs_mut()
+                .append_pair("method", "list")
+                .append_pair("filetype", "dir")
+                .append_pair("folder_path", path);
+        }
+        let resp = self.service.send_request(url, Method::GET, None)?;
+
+        if resp["success"] != true {
+            warn!("Could not list folder {path}: {resp}");
+            return None;
+        }
+        let key = if path.is_empty() { "shares" } else { "files" };
+        let folders = resp["data"][key].as_array()?.iter().map(|f| Folder {
+            name: f["name"].as_str().unwrap_or_default().to_string(),
+            writable: is_writable(&f["additional"]["perm"]),
+        });
+        Some(folders.collect())
+    }
+
+    fn create_folder(&self, parent: &str, name: &str) -> bool {
+        let mut url = Url::parse(&format!(
+            "{}?api=SYNO.FileStation.CreateFolder&version=2&method=create&force_parent=true",
+            self.conf.root_api
+        ))
+        .unwrap();
+        url.query_pairs_mut()
+            .append_pair("folder_path", parent)
+            .append_pair("name", name);
+        match self.service.send_request(url, Method::GET, None) {
+            Some(resp) if resp["success"] == true => true,
+            Some(resp) => {
+                warn!("Could not create {parent}/{name}: {resp}");
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn list_files(&self, path: &str) -> Option<Vec<Entry>> {
+        match self.file_station("List", 2, "list", &[("folder_path", path)]) {
+            Ok(data) => Some(
+                data["files"]
+                    .as_array()?
+                    .iter()
+                    .map(|f| Entry {
+                        name: f["name"].as_str().unwrap_or_default().to_string(),
+                        is_dir: f["isdir"].as_bool().unwrap_or(false),
+                    })
+                    .collect(),
+            ),
+            Err(e) => {
+                warn!("Could not list {path}: {e}");
+                None
+            }
+        }
+    }
+
+    fn move_to(&self, path: &str, folder: &str) -> Result<(), String> {
+        let params = [
+            ("path", path),
+            ("dest_folder_path", folder),
+            ("overwrite", "false"),
+            ("remove_src", "true"),
+        ];
+        self.background("CopyMove", 3, &params)
+            .map_err(|e| format!("could not move {path} to {folder}, {e}"))
+    }
+
+    fn rename(&self, path: &str, name: &str) -> Result<(), String> {
+        self.file_station("Rename", 2, "rename", &[("path", path), ("name", name)])
+            .map(|_| ())
+            .map_err(|e| format!("could not rename {path} to {name}, {e}"))
+    }
+
+    fn extract(&self, archive: &str, folder: &str) -> Result<(), String> {
+        let params = [
+            ("file_path", archive),
+            ("dest_folder_path", folder),
+            ("keep_dir", "true"),
+            ("create_subfolder", "false"),
+            ("overwrite", "false"),
+        ];
+        self.background("Extract", 2, &params)
+            .map_err(|e| format!("could not extract {archive}, {e}"))
+    }
+
+    fn delete(&self, path: &str) -> Result<(), String> {
+        self.background("Delete", 2, &[("path", path), ("recursive", "true")])
+            .map_err(|e| format!("could not delete {path}, {e}"))
+    }
+
+    fn free_space(&self, share: &str) -> Option<Space> {
+        let data = self
+            .file_station("List", 2, "list_share", &[("additional", "volume_status")])
+            .map_err(|e| warn!("Could not list shared folders: {e}"))
+            .ok()?;
+        let share = data["shares"]
+            .as_array()?
+            .iter()
+            .find(|s| s["name"] == share)?;
+        let volume = &share["additional"]["volume_status"];
+        Some(Space {
+            free: volume["freespace"].as_u64()?,
+            total: volume["totalspace"].as_u64()?,
+        })
+    }
+}
+
+// Shares tell their rights, folders their ACL. Folders whose
+// permissions are unknown are assumed writable
+fn is_writable(perm: &Value) -> bool {
+    match perm["share_right"].as_str() {
+        Some(right) => right == "RW",
+        None => perm["acl"]["write"].as_bool().unwrap_or(true),
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::RefCell;
+
+    use bytes::Bytes;
+    use reqwest::{Method, Url};
+    use serde_json::{json, Value};
+
+    use crate::{
+        conf::SynologyConf,
+        core::traits::{HTTPService, Payload, StorageController},
+        services::download_station::DsControler,
+    };
+
+    struct DsServiceMock {
+        urls: RefCell<Vec<Url>>,
+    }
+
+    impl HTTPService for DsServiceMock {
+        type Conf = SynologyConf;
+
+        fn new(_: &SynologyConf) -> Self {
+            Self {
+                urls: RefCell::new(vec![]),
+            }
+        }
+        fn send_request(&self, url: Url, _: Method, _: Option<Payload>) -> Option<Value> {
+            self.urls.borrow_mut().push(url.clone());
+            if url.query().unwrap().contains("list_share") {
+                Some(json!({"success": true, "data": {"shares": [
+                    {"name": "video", "path": "/video", "isdir": true, "additional": {"perm": {"share_right": "RW"}}},
+                    {"name": "photo", "path": "/photo", "isdir": true, "additional": {"perm": {"share_right": "RO"}}}
+                ]}}))
+            } else if url.query().unwrap().contains("method=start") {
+                Some(json!({"success": true, "data": {"taskid": "FileStation_1"}}))
+            } else if url.query().unwrap().contains("method=status") {
+                Some(json!({"success": true, "data": {"finished": true, "progress": 1.0}}))
+            } else if url.query().unwrap().contains("method=list") {
+                Some(json!({"success": true, "data": {"files": [
+                    {"name": "Movies", "path": "/video/Movies", "isdir": true, "additional": {"perm": {"acl": {"write": false}}}}
+                ]}}))
+            } else {
+                Some(json!({"success": true}))
+            }
+        }
+        fn download_file(&self, _: Url) -> Option<Bytes> {
+            panic!("Not implemented")
+        }
+    }
+
+    fn conf() -> SynologyConf {
+        SynologyConf {
+            root_api: String::from("http://127.0.0.1:5000"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn folder_listing() {
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        let shares = controler.list_folders("").unwrap();
+        assert!(shares.len() == 2);
+        assert!(shares[0].name == "video" && shares[0].writable);
+        assert!(!shares[1].writable);
+
+        let folders = controler.list_folders("/video").unwrap();
+        assert!(folders[0].name == "Movies" && !folders[0].writable);
+        let url = controler.service.urls.borrow()[1].clone();
+        assert!(url
+            .query_pairs()
+            .any(|(k, v)| k == "folder_path" && v == "/video"));
+
+        assert!(controler.create_folder("/video", "Movies/2024"));
+        let url = controler.service.urls.borrow()[2].clone();
+        assert!(url
+            .query()
+            .unwrap()
+            .contains("SYNO.FileStation.CreateFolder"));
+        assert!(url
+            .query_pairs()
+            .any(|(k, v)| k == "name" && v == "Movies/2024"));
+    }
+
+    #[test]
+    fn background_operations() {
+        let controler = DsControler::<DsServiceMock>::new(conf());
+        assert!(controler.move_to("/video/a.mkv", "/video/Movies").is_ok());
+        let urls = controler.service.urls.borrow();
+        assert!(urls[0]
+            .query()
+            .unwrap()
+            .contains("SYNO.FileStation.CopyMove"));
+        assert!(urls[0]
+            .query_pairs()
+            .any(|(k, v)| k == "dest_folder_path" && v == "/video/Movies"));
+        assert!(urls[1]
+            .query_pairs()
+            .any(|(k, v)| k == "taskid" && v == "FileStation_1"));
+    }
+}
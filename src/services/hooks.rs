@@ -0,0 +1,264 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use reqwest::{Method, Url};
+use serde_json::{json, Value};
+
+use crate::conf::HookConf;
+use crate::core::destination;
+use crate::core::task::{Source, Task};
+
+use super::API_USER_AGENT;
+
+// Commands still running after that are killed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+// Prefix of the env vars given to commands
+const ENV_PREFIX: &str = "DS_COMPANION_";
+
+// Actions run once a download is over
+pub struct Hooks {
+    hooks: Vec<HookConf>,
+    client: Client,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookConf>) -> Self {
+        let client = Client::builder().timeout(HTTP_TIMEOUT).build().unwrap();
+        Self { hooks, client }
+    }
+
+    // Run the hooks of the folder the task was downloaded to, and the
+    // global ones. Returns what happened, one line per action
+    pub fn run(&self, task: &Task, destination: Option<&str>) -> Vec<String> {
+        let metadata = metadata(task, destination);
+        let mut outcomes = vec![];
+        for hook in self
+            .hooks
+            .iter()
+            .filter(|h| destination::contains(h.folder.as_deref(), destination))
+        {
+            if let Some(command) = &hook.command {
+                let name = command.split_whitespace().next().unwrap_or_default();
+                outcomes.push(outcome("command", name, run_command(command, &metadata)));
+            }
+            if let Some(webhook) = &hook.webhook {
+                let result = self.call(Method::POST, webhook, Some(&metadata));
+                outcomes.push(outcome("webhook", &host(webhook), result));
+            }
+            if let Some(rescan) = &hook.rescan {
+                // "GET http://..." to use another method than POST
+                let (method, url) = match rescan.split_once(' ') {
+                    Some((method, url)) => (
+                        Method::from_bytes(method.to_uppercase().as_bytes())
+                            .unwrap_or(Method::POST),
+                        url.trim(),
+                    ),
+                    None => (Method::POST, rescan.as_str()),
+                };
+                outcomes.push(outcome("rescan", &host(url), self.call(method, url, None)));
+            }
+        }
+        outcomes
+    }
+
+    fn call(&self, method: Method, url: &str, body: Option<&Value>) -> Result<(), String> {
+        let url = Url::parse(url).map_err(|e| format!("invalid URL: {e}"))?;
+        let mut request = self
+            .client
+            .request(method, url)
+            .header(USER_AGENT, API_USER_AGENT);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        match request.send() {
+            Ok(resp) if resp.status().is_success() => Ok(()),
+            Ok(resp) => Err(format!("HTTP {}", resp.status().as_u16())),
+            Err(e) => Err(format!("unreachable ({e})")),
+        }
+    }
+}
+
+fn outcome(kind: &str, target: &str, result: Result<(), String>) -> String {
+    match result {
+        Ok(()) => {
+            info!("Hook {kind} {target} succeeded");
+            format!("{kind} {target}: ok")
+        }
+        Err(e) => {
+            warn!("Hook {kind} {target} failed: {e}");
+            format!("{kind} {target}: failed, {e}")
+        }
+    }
+}
+
+// Host of an URL, its path and query may hold tokens
+fn host(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or(String::from("?"))
+}
+
+// What hooks are told about the download
+fn metadata(task: &Task, destination: Option<&str>) -> Value {
+    let uri = match &task.source {
+        Source::MAGNET(link) | Source::URL(link) => Some(link.clone()),
+        Source::FILE(_) => None,
+    };
+    json!({
+        "message_id": task.message_id,
+        "channel_id": task.channel_id,
+        "user_id": task.user_id,
+        "nas": task.nas,
+        "status": task.get_status(),
+        "name": task.request.name.clone().or(task.source.name()),
+        "destination": destination,
+        "size": task.size,
+        "uri": uri,
+        "files": task.selected_files,
+    })
+}
+
+// The command gets the metadata as JSON on stdin and as DS_COMPANION_*
+// env vars, through sh so that it may hold arguments
+fn run_command(command: &str, metadata: &Value) -> Result<(), String> {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command).stdin(Stdio::piped());
+    for (key, value) in metadata.as_object().unwrap() {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(s) => s.clone(),
+            Value::Array(items) => items
+                .iter()
+                .map(|i| i.as_str().unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            value => value.to_string(),
+        };
+        process.env(format!("{ENV_PREFIX}{}", key.to_uppercase()), value);
+    }
+    let mut child = process
+        .spawn()
+        .map_err(|e| format!("could not start ({e})"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the command may not read its input
+        let _ = stdin.write_all(metadata.to_string().as_bytes());
+    }
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("exited with {status}")),
+            Ok(None) if started.elapsed() > COMMAND_TIMEOUT => {
+                let _ = child.kill();
+                return Err(String::from("timed out"));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/////Unit Tests/////
+
+#[cfg(test)]
+pub mod tests {
+    use std::{env, fs, thread};
+
+    use serde_json::Value;
+    use tiny_http::{Response, Server};
+
+    use crate::{
+        conf::HookConf,
+        core::{
+            notifier::LogNotifier,
+            task::{Source, Task},
+        },
+        services::hooks::Hooks,
+    };
+
+    #[test]
+    fn hooks_run() {
+        // a local stub standing for the webhook and the media server
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let address = server.server_addr().to_ip().unwrap();
+        let stub = thread::spawn(move || {
+            let mut received = vec![];
+            for _ in 0..2 {
+                let mut request = server.recv().unwrap();
+                let mut body = String::new();
+                request.as_reader().read_to_string(&mut body).unwrap();
+                received.push((
+                    request.method().to_string(),
+                    request.url().to_string(),
+                    body,
+                ));
+                let status = match request.url() {
+                    "/rescan" => 500,
+                    _ => 200,
+                };
+                request.respond(Response::empty(status)).unwrap();
+            }
+            received
+        });
+
+        let output = env::temp_dir().join(format!("ds-companion-hook-{}", std::process::id()));
+        let hooks = Hooks::new(vec![
+            HookConf {
+                command: Some(format!(
+                    "cat > {0} && echo \"$DS_COMPANION_NAME\" >> {0}",
+                    output.display()
+                )),
+                webhook: Some(format!("http://{address}/hook")),
+                ..Default::default()
+            },
+            HookConf {
+                folder: Some(String::from("video")),
+                rescan: Some(format!("GET http://{address}/rescan")),
+                ..Default::default()
+            },
+            HookConf {
+                folder: Some(String::from("music")),
+                command: Some(String::from("false")),
+                ..Default::default()
+            },
+        ]);
+        let notifier = LogNotifier;
+        // no destination asked for: Download Station's default one
+        let task = Task::new(
+            Source::URL(String::from("https://example.org/debian.iso")),
+            String::from("1"),
+            String::from("2"),
+            &notifier,
+            None,
+            String::from("3"),
+        );
+        let outcomes = hooks.run(&task, Some("video/Movies"));
+        assert!(
+            outcomes
+                == [
+                    "command cat: ok",
+                    "webhook 127.0.0.1: ok",
+                    "rescan 127.0.0.1: failed, HTTP 500"
+                ]
+        );
+
+        let received = stub.join().unwrap();
+        assert!(received[0].0 == "POST" && received[0].1 == "/hook");
+        let body: Value = serde_json::from_str(&received[0].2).unwrap();
+        assert!(body["name"] == "debian.iso" && body["destination"] == "video/Movies");
+        assert!(received[1].0 == "GET" && received[1].1 == "/rescan");
+
+        let written = fs::read_to_string(&output).unwrap();
+        let (stdin, name) = written.split_once('}').unwrap();
+        assert!(serde_json::from_str::<Value>(&format!("{stdin}}}")).unwrap()["user_id"] == "3");
+        assert!(name.trim() == "debian.iso");
+        fs::remove_file(output).unwrap();
+    }
+}
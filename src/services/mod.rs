@@ -1,5 +1,8 @@
 pub mod discord;
 pub mod download_station;
+pub mod feeds;
+pub mod file_station;
+pub mod hooks;
 mod schemas;
 
 pub const API_USER_AGENT: &str = "Download-Station-Companion";
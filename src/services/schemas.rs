@@ -3,7 +3,7 @@ use serde::Deserialize;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::core::task::TaskStatus;
+use crate::core::task::{RemoteTask, TaskStatus};
 
 #[allow(dead_code)]
 #[derive(Deserialize)]
@@ -38,6 +38,9 @@ pub struct ApiInformation {
     pub auth: SynoApiAuth,
     #[serde(rename = "SYNO.DownloadStation.Task")]
     pub task: SynoDsControlerTask,
+    // every other API (FileStation...), by name
+    #[serde(flatten)]
+    pub others: HashMap<String, SynoDsControlerTask>,
 }
 
 #[allow(dead_code)]
@@ -49,6 +52,47 @@ pub struct AttachementObject {
     pub proxy_url: String,
 }
 
+#[derive(Deserialize, Debug, Default)]
+pub struct DsTaskDetail {
+    pub destination: Option<String>,
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub seedelapsed: u64,
+    // timestamps, 0 when unknown
+    #[serde(default)]
+    pub create_time: i64,
+    #[serde(default)]
+    pub completed_time: i64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DsTaskTransfer {
+    #[serde(default)]
+    pub size_downloaded: u64,
+    #[serde(default)]
+    pub size_uploaded: u64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DsTaskAdditional {
+    #[serde(default)]
+    pub detail: DsTaskDetail,
+    #[serde(default)]
+    pub transfer: DsTaskTransfer,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DsTaskObject {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub size: u64,
+    pub status: String,
+    #[serde(default)]
+    pub additional: DsTaskAdditional,
+}
+
 pub static DS_TO_COMPANION_MAPPING: Lazy<Arc<HashMap<&'static str, TaskStatus>>> =
     Lazy::new(|| {
         let hash = HashMap::from([
@@ -82,3 +126,26 @@ pub static DS_ERROR_CODES: Lazy<Arc<HashMap<u8, &str>>> = Lazy::new(|| {
     ]);
     Arc::new(mapping)
 });
+
+impl From<DsTaskObject> for RemoteTask {
+    fn from(obj: DsTaskObject) -> Self {
+        let status = DS_TO_COMPANION_MAPPING
+            .get(obj.status.as_str())
+            .copied()
+            .unwrap_or(TaskStatus::SUBMITTED);
+        RemoteTask {
+            id: obj.id,
+            title: obj.title,
+            status,
+            ds_status: obj.status,
+            size: obj.size,
+            downloaded: obj.additional.transfer.size_downloaded,
+            uploaded: obj.additional.transfer.size_uploaded,
+            seeded_for: obj.additional.detail.seedelapsed,
+            created_at: Some(obj.additional.detail.create_time).filter(|t| *t > 0),
+            completed_at: Some(obj.additional.detail.completed_time).filter(|t| *t > 0),
+            uri: obj.additional.detail.uri,
+            destination: obj.additional.detail.destination,
+        }
+    }
+}